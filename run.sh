@@ -1,2 +1,2 @@
 #!/bin/bash
-cargo run master host
+cargo run -- host --name master
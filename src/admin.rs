@@ -0,0 +1,62 @@
+use std::{io::BufRead, sync::mpsc, thread};
+
+pub const POMOC_UKAZI: &str = "commands: /players, /kick <name|id>, /ban <name|id|ip>, /unban <ip>, /map <name>, /restart, /set <setting> <value>";
+pub const NASTAVITVE: &str = "settings: respawn_time <s>, send_rate <hz>, max_rewind <ms>, timeout <s>";
+
+/// ukazi za upravljanje streznika iz konzole namenskega streznika ali iz klepeta gostitelja
+pub enum AdminUkaz {
+    Pomoc,
+    Igralci,
+    Kick(String),
+    Ban(String),
+    Unban(String),
+    Mapa(String),
+    Restart,
+    Set(String, String),
+}
+
+/// vodilna / ni obvezna, da se v konzoli lahko pise brez nje
+pub fn razcleni_ukaz(vrstica: &str) -> Result<AdminUkaz, String> {
+    let vrstica = vrstica.trim();
+    let vrstica = vrstica.strip_prefix('/').unwrap_or(vrstica);
+    let mut deli = vrstica.split_whitespace();
+    let ukaz = deli.next().unwrap_or("");
+    let argumenti: Vec<String> = deli.map(|d| d.to_string()).collect();
+
+    match (ukaz, argumenti.as_slice()) {
+        ("help", []) => Ok(AdminUkaz::Pomoc),
+        ("players", []) => Ok(AdminUkaz::Igralci),
+        ("kick", [kdo]) => Ok(AdminUkaz::Kick(kdo.clone())),
+        ("ban", [kdo]) => Ok(AdminUkaz::Ban(kdo.clone())),
+        ("unban", [ip]) => Ok(AdminUkaz::Unban(ip.clone())),
+        ("map", [ime]) => Ok(AdminUkaz::Mapa(ime.clone())),
+        ("restart", []) => Ok(AdminUkaz::Restart),
+        ("set", [kaj, vrednost]) => Ok(AdminUkaz::Set(kaj.clone(), vrednost.clone())),
+        ("", _) => Err(POMOC_UKAZI.to_string()),
+        _ => Err(format!("unknown command or wrong arguments: {}\n{}", vrstica, POMOC_UKAZI)),
+    }
+}
+
+/// bere vrstice s standardnega vhoda v svoji niti, da ne blokira zanke streznika
+pub struct Konzola {
+    rx: mpsc::Receiver<String>,
+}
+
+impl Konzola {
+    pub fn zazeni() -> Konzola {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for vrstica in std::io::stdin().lock().lines() {
+                let Ok(vrstica) = vrstica else { break };
+                if tx.send(vrstica).is_err() {
+                    break;
+                }
+            }
+        });
+        Konzola { rx }
+    }
+
+    pub fn naslednja_vrstica(&self) -> Option<String> {
+        self.rx.try_recv().ok()
+    }
+}
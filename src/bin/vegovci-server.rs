@@ -0,0 +1,9 @@
+use vegovci_multiplayer::{cli, dedicated};
+
+fn main() {
+    // enako kot `vegovci-multiplayer server`
+    let args = std::iter::once("server".to_string()).chain(std::env::args().skip(1));
+    let nastavitve = cli::razcleni(args).unwrap_or_else(|e| cli::izpisi_in_koncaj(e));
+    println!("pozdravljen svet!");
+    dedicated::zazeni(&nastavitve);
+}
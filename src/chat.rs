@@ -0,0 +1,61 @@
+use macroquad::prelude::*;
+
+use crate::{screen_units_width, screen_units_height, KAMERA_POS, MAX_DOLZINA_SPOROCILA};
+
+/// polje za pisanje v klepet, odpre se z Enter
+#[derive(Default)]
+pub struct Klepet {
+    odprt: bool,
+    tekst: String,
+}
+
+impl Klepet {
+    pub fn odprt(&self) -> bool {
+        self.odprt
+    }
+
+    /// klice se vsak frame, vrne sporocilo, ko ga igralec potrdi z Enter
+    pub fn posodobi(&mut self) -> Option<String> {
+        if !self.odprt {
+            if is_key_pressed(KeyCode::Enter) {
+                self.odprt = true;
+                self.tekst.clear();
+                // crke, natipkane pred odprtjem, ne spadajo v sporocilo
+                while get_char_pressed().is_some() {}
+            }
+            return None;
+        }
+
+        while let Some(c) = get_char_pressed() {
+            if !c.is_control() && self.tekst.chars().count() < MAX_DOLZINA_SPOROCILA {
+                self.tekst.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.tekst.pop();
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            self.odprt = false;
+        } else if is_key_pressed(KeyCode::Enter) {
+            self.odprt = false;
+            return Some(std::mem::take(&mut self.tekst));
+        }
+        None
+    }
+
+    pub fn narisi(&self) {
+        if !self.odprt {
+            return;
+        }
+
+        // pod stevcem fps, da ne prekrije sporocil
+        let pos = vec2(-screen_units_width() + 2.0, -screen_units_height() + 14.0) + KAMERA_POS.get();
+        draw_rectangle(pos.x, pos.y, 2.0 * screen_units_width() - 4.0, 12.0, Color::new(0.0, 0.0, 0.0, 0.6));
+        draw_text_ex(&format!("say: {}_", self.tekst), pos.x + 1.0, pos.y + 9.0, TextParams {
+            font_size: 32,
+            font_scale: 0.35,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,207 @@
+use std::{net::IpAddr, time::Duration};
+
+use crate::{PORT, PRIVZETA_INTERPOLACIJA, PRIVZETI_MAX_REWIND, PRIVZETI_TIMEOUT, PRIVZETO_MAX_IGRALCEV, TICK_TIME, preveri_ime};
+
+/// pri pocasnejsem ticku bi bil korak daljsi od MAX_DELTA
+const MIN_TICK_RATE: f32 = 15.0;
+const MAX_TICK_RATE: f32 = 240.0;
+
+pub const POMOC: &str = "\
+usage: vegovci-multiplayer <command> [options]
+
+commands:
+  host                 host a game and play in it
+  join [addr[:port]]   join a game, without an address shows LAN games
+  server               run a headless dedicated server
+
+options:
+  -n, --name <name>        player name (required for host and join)
+      --bind <ip>          address to listen on (host, server) [default: 0.0.0.0]
+  -p, --port <port>        port to listen on (host, server) [default: 5356]
+      --map <path>         map image [default: assets/map.png]
+      --max-players <n>    players allowed at once, others wait in a queue (host, server) [default: 16]
+      --password <pw>      password required to join (host, server) or sent when joining (join)
+      --bans <path>        file with banned ip addresses (host, server) [default: bans.txt]
+      --tick-rate <hz>     simulation ticks per second, 15 to 240 [default: 60]
+      --send-rate <hz>     network sends per second
+      --interp <ms>        interpolation delay for other players [default: 100]
+      --timeout <s>        drop connections silent for this long [default: 10]
+      --max-rewind <ms>    max lag compensation for hits [default: 200]
+      --tcp                connect over tcp instead of udp (join)
+      --spectate           watch the game without playing (join)
+      --width <px>         window width [default: 800]
+      --height <px>        window height [default: 600]
+      --colliders          draw colliders
+  -h, --help               print this help";
+
+pub enum Ukaz {
+    Host,
+    /// brez naslova se odpre brskalnik iger v lokalnem omrezju
+    Join(Option<String>),
+    Server,
+}
+
+pub struct Nastavitve {
+    pub ukaz: Ukaz,
+    pub ime: String,
+    pub bind: IpAddr,
+    pub port: u16,
+    pub mapa: String,
+    pub bani: String,
+    pub geslo: Option<String>,
+    pub max_igralcev: usize,
+    pub tick_rate: f32,
+    pub send_rate: Option<f32>,
+    pub interpolacija: Duration,
+    pub timeout: Duration,
+    pub max_rewind: Duration,
+    pub tcp: bool,
+    pub gledalec: bool,
+    pub sirina: i32,
+    pub visina: i32,
+    pub colliders: bool,
+}
+
+pub enum CliNapaka {
+    /// --help, izpise se POMOC
+    Pomoc,
+    Napaka(String),
+}
+
+impl Nastavitve {
+    pub fn tick_time(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    /// ime mape za oglas v lokalnem omrezju
+    pub fn ime_mape(&self) -> String {
+        let ime = self.mapa.rsplit(['/', '\\']).next().unwrap_or(&self.mapa);
+        ime.strip_suffix(".png").unwrap_or(ime).to_string()
+    }
+}
+
+fn napaka(sporocilo: String) -> CliNapaka {
+    CliNapaka::Napaka(sporocilo)
+}
+
+fn stevilo<T: std::str::FromStr + PartialOrd + Default>(opcija: &str, vrednost: &str) -> Result<T, CliNapaka> {
+    match vrednost.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(napaka(format!("invalid value for {}: {}", opcija, vrednost))),
+    }
+}
+
+/// prvi argument je ukaz, ostali so opcije kot --opcija=vrednost ali --opcija vrednost
+pub fn razcleni(args: impl IntoIterator<Item = String>) -> Result<Nastavitve, CliNapaka> {
+    let mut args = args.into_iter();
+
+    let mut n = Nastavitve {
+        ukaz: Ukaz::Host,
+        ime: String::new(),
+        bind: IpAddr::from([0, 0, 0, 0]),
+        port: PORT,
+        mapa: "assets/map.png".to_string(),
+        bani: "bans.txt".to_string(),
+        geslo: None,
+        max_igralcev: PRIVZETO_MAX_IGRALCEV,
+        tick_rate: 1.0 / TICK_TIME,
+        send_rate: None,
+        interpolacija: PRIVZETA_INTERPOLACIJA,
+        timeout: PRIVZETI_TIMEOUT,
+        max_rewind: PRIVZETI_MAX_REWIND,
+        tcp: false,
+        gledalec: false,
+        sirina: 800,
+        visina: 600,
+        colliders: false,
+    };
+
+    let mut ukaz = None;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            match (&ukaz, arg.as_str()) {
+                (None, "host") => ukaz = Some(Ukaz::Host),
+                (None, "join") => ukaz = Some(Ukaz::Join(None)),
+                (None, "server") => ukaz = Some(Ukaz::Server),
+                (Some(Ukaz::Join(None)), _) => ukaz = Some(Ukaz::Join(Some(arg))),
+                (None, _) => return Err(napaka(format!("unknown command: {}", arg))),
+                (Some(_), _) => return Err(napaka(format!("unexpected argument: {}", arg))),
+            }
+            continue;
+        }
+
+        let (opcija, vrednost) = match arg.split_once('=') {
+            Some((o, v)) => (o.to_string(), Some(v.to_string())),
+            None => (arg.clone(), None),
+        };
+
+        // zastavice brez vrednosti
+        match opcija.as_str() {
+            "-h" | "--help" => return Err(CliNapaka::Pomoc),
+            "--tcp" => { n.tcp = true; continue; },
+            "--colliders" => { n.colliders = true; continue; },
+            "--spectate" => { n.gledalec = true; continue; },
+            _ => {},
+        }
+
+        let Some(vrednost) = vrednost.or_else(|| args.next()) else {
+            return Err(napaka(format!("missing value for {}", opcija)));
+        };
+        match opcija.as_str() {
+            "-n" | "--name" => n.ime = vrednost,
+            "--bind" => n.bind = vrednost.parse().map_err(|_| napaka(format!("invalid ip address: {}", vrednost)))?,
+            "-p" | "--port" => n.port = stevilo(&opcija, &vrednost)?,
+            "--map" => n.mapa = vrednost,
+            "--bans" => n.bani = vrednost,
+            "--max-players" => n.max_igralcev = stevilo(&opcija, &vrednost)?,
+            "--password" if vrednost.is_empty() => return Err(napaka("password must not be empty".to_string())),
+            "--password" => n.geslo = Some(vrednost),
+            "--tick-rate" => n.tick_rate = stevilo(&opcija, &vrednost)?,
+            "--send-rate" => n.send_rate = Some(stevilo(&opcija, &vrednost)?),
+            "--interp" => n.interpolacija = Duration::from_millis(stevilo(&opcija, &vrednost)?),
+            "--timeout" => n.timeout = Duration::from_secs(stevilo(&opcija, &vrednost)?),
+            "--max-rewind" => n.max_rewind = Duration::from_millis(stevilo(&opcija, &vrednost)?),
+            "--width" => n.sirina = stevilo(&opcija, &vrednost)?,
+            "--height" => n.visina = stevilo(&opcija, &vrednost)?,
+            _ => return Err(napaka(format!("unknown option: {}", opcija))),
+        }
+    }
+
+    n.ukaz = match ukaz {
+        Some(ukaz) => ukaz,
+        None => return Err(napaka("missing command".to_string())),
+    };
+
+    if !(MIN_TICK_RATE..=MAX_TICK_RATE).contains(&n.tick_rate) {
+        return Err(napaka(format!("tick rate must be between {} and {}", MIN_TICK_RATE, MAX_TICK_RATE)));
+    }
+
+    if n.gledalec && !matches!(n.ukaz, Ukaz::Join(_)) {
+        return Err(napaka("--spectate only works with join".to_string()));
+    }
+
+    if !matches!(n.ukaz, Ukaz::Server) {
+        if n.ime.is_empty() {
+            return Err(napaka("missing --name".to_string()));
+        }
+        preveri_ime(&n.ime).map_err(napaka)?;
+    }
+
+    Ok(n)
+}
+
+/// izpise pomoc ali napako in konca program
+pub fn izpisi_in_koncaj(napaka: CliNapaka) -> ! {
+    match napaka {
+        CliNapaka::Pomoc => {
+            println!("{}", POMOC);
+            std::process::exit(0)
+        },
+        CliNapaka::Napaka(sporocilo) => {
+            eprintln!("ERROR {}", sporocilo);
+            eprintln!("{}", POMOC);
+            std::process::exit(1)
+        },
+    }
+}
@@ -84,7 +84,7 @@ impl<T> FreeList<T> {
     }
 
     pub fn vstavi(&mut self, el: T) -> usize {
-        if self.free.len() > 0 {
+        if !self.free.is_empty() {
             let i = self.free.pop().unwrap();
             self.elements[i] = Some(el);
             return i;
@@ -112,6 +112,9 @@ struct Objekt {
 pub const LAYER_MAP: u32 = 1 << 0;
 pub const LAYER_PLAYER: u32 = 1 << 1;
 pub const LAYER_SWORD: u32 = 1 << 2;
+/// igralec, ki ga simulira odjemalec, ne trka z igralci na strezniku
+pub const LAYER_LOKALNI: u32 = 1 << 3;
+pub const LAYER_LOKALNI_MEC: u32 = 1 << 4;
 
 pub struct DinamicenAABBRef(usize);
 pub struct StaticenAABBRef(usize);
@@ -236,6 +239,18 @@ pub mod physics {
         }
     }
 
+    /// resi trke enega dinamicnega objekta s staticnimi
+    pub fn resi_trke_obj(aabb_ref: &DinamicenAABBRef) {
+        let mut physics_mutex_guard = GLOBAL_PHYSICS.lock().unwrap();
+        let physics = physics_mutex_guard.as_mut().unwrap();
+
+        let mut obj = physics.dinamicni.elements[aabb_ref.0].clone().unwrap();
+        for staticen in physics.staticni.elements.iter_mut().flatten() {
+            resi_trk(staticen, &mut obj, 0.0, 1.0);
+        }
+        physics.dinamicni.elements[aabb_ref.0] = Some(obj);
+    }
+
     pub fn resi_trke() {
         let mut physics_mutex_guard = GLOBAL_PHYSICS.lock().unwrap();
         let physics = physics_mutex_guard.as_mut().unwrap();
@@ -271,11 +286,9 @@ pub mod physics {
 
         let mut result = Vec::new();
 
-        for obj in physics.dinamicni.elements.iter() {
-            if let Some(o) = obj {
-                if (mask & o.layer) > 0 && o.aabb.overlaps(area) {
-                    result.push((o.user_id, o.aabb));
-                }
+        for o in physics.dinamicni.elements.iter().flatten() {
+            if (mask & o.layer) > 0 && o.aabb.overlaps(area) {
+                result.push((o.user_id, o.aabb));
             }
         }
 
@@ -286,16 +299,12 @@ pub mod physics {
         let mut physics_mutex_guard = GLOBAL_PHYSICS.lock().unwrap();
         let physics = physics_mutex_guard.as_mut().unwrap();
 
-        for obj in physics.dinamicni.elements.iter() {
-            if let Some(o) = obj {
-                draw_rectangle_lines(o.aabb.x, o.aabb.y, o.aabb.w, o.aabb.h, 1.0, BLUE);
-            }
+        for o in physics.dinamicni.elements.iter().flatten() {
+            draw_rectangle_lines(o.aabb.x, o.aabb.y, o.aabb.w, o.aabb.h, 1.0, BLUE);
         }
 
-        for obj in physics.staticni.elements.iter() {
-            if let Some(o) = obj {
-                draw_rectangle_lines(o.aabb.x, o.aabb.y, o.aabb.w, o.aabb.h, 1.0, RED);
-            }
+        for o in physics.staticni.elements.iter().flatten() {
+            draw_rectangle_lines(o.aabb.x, o.aabb.y, o.aabb.w, o.aabb.h, 1.0, RED);
         }
     }
 
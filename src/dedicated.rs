@@ -0,0 +1,80 @@
+use macroquad::prelude::*;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::admin::{self, Konzola};
+use crate::{cli::Nastavitve, generate_map_colliders, physics, Server, MAP_POS, MAX_DELTA};
+
+fn nalozi_sliko(pot: &str) -> Option<Image> {
+    let bytes = std::fs::read(pot).ok()?;
+    Image::from_file_with_format(&bytes, Some(ImageFormat::Png)).ok()
+}
+
+/// namenski streznik brez okna, tece dokler ga ne ustavis
+pub fn zazeni(nastavitve: &Nastavitve) -> ! {
+    println!("v nacinu namenskega streznika!");
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    rand::srand(seed);
+
+    let bind = SocketAddr::new(nastavitve.bind, nastavitve.port);
+    let mut server = match Server::dedicated(bind) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("ERROR {}", e);
+            std::process::exit(1);
+        }
+    };
+    server.max_rewind = nastavitve.max_rewind;
+    server.timeout = nastavitve.timeout;
+    server.mapa = nastavitve.ime_mape();
+    server.nalozi_bane(nastavitve.bani.clone().into());
+    server.geslo = nastavitve.geslo.clone();
+    server.max_igralcev = nastavitve.max_igralcev;
+    if let Some(hz) = nastavitve.send_rate {
+        server.hitrost_posiljanja = hz;
+    }
+    println!("listening on {}", bind);
+
+    physics::init();
+
+    let map_image = nalozi_sliko(&nastavitve.mapa).unwrap_or_else(|| {
+        eprintln!("ERROR could not load map {}", nastavitve.mapa);
+        std::process::exit(1);
+    });
+    let mut map_aabb_refs = generate_map_colliders(map_image, MAP_POS);
+
+    println!("stevilo staticnih objektov: {}", physics::st_staticnih_obj());
+
+    let tick_time = nastavitve.tick_time();
+    let tick = Duration::from_secs_f32(tick_time);
+    let mut naslednji_tick = Instant::now();
+    let konzola = Konzola::zazeni();
+    println!("{}", admin::POMOC_UKAZI);
+
+    loop {
+        while let Some(vrstica) = konzola.naslednja_vrstica() {
+            println!("{}", server.izvedi_ukaz(&vrstica));
+        }
+        if let Some(pot) = server.nova_mapa.take() {
+            match nalozi_sliko(&pot) {
+                Some(slika) => {
+                    map_aabb_refs.clear();
+                    map_aabb_refs = generate_map_colliders(slika, MAP_POS);
+                },
+                None => eprintln!("err could not load map {}", pot),
+            }
+        }
+
+        server.tick(tick_time);
+
+        // ce zaostajamo, ticki tecejo brez cakanja, dokler ne ujamemo ure
+        naslednji_tick += tick;
+        let zdaj = Instant::now();
+        if naslednji_tick > zdaj {
+            std::thread::sleep(naslednji_tick - zdaj);
+        } else if zdaj - naslednji_tick > Duration::from_secs_f32(MAX_DELTA) {
+            naslednji_tick = zdaj;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{io::{self, ErrorKind}, net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket}, time::{Duration, Instant}};
+use serde::{Serialize, Deserialize};
+
+use crate::PROTOCOL_VERSION;
+
+/// na tem portu streznik odgovarja na iskanje v lokalnem omrezju
+pub const DISCOVERY_PORT: u16 = 5357;
+/// da ne odgovarjamo na tuje pakete
+const MAGIC: &[u8; 4] = b"VGVC";
+/// kako pogosto odjemalec poslje broadcast
+const INTERVAL_ISKANJA: Duration = Duration::from_secs(1);
+/// streznik, ki se toliko casa ni oglasil, ni vec na seznamu
+const POZABI_PO: Duration = Duration::from_secs(4);
+const MAX_PAKET: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OglasStreznika {
+    pub ime: String,
+    pub mapa: String,
+    pub igralci: u32,
+    pub max_igralcev: u32,
+    /// port za igro, lahko je drugacen od DISCOVERY_PORT
+    pub port: u16,
+    pub protocol_version: u32,
+    /// za povezavo je potrebno geslo
+    pub geslo: bool,
+}
+
+impl OglasStreznika {
+    pub fn zdruzljiv(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Discovery {
+    Poizvedba,
+    Oglas(OglasStreznika),
+}
+
+fn zapakiraj(msg: &Discovery) -> io::Result<Vec<u8>> {
+    let mut paket = MAGIC.to_vec();
+    paket.append(&mut bincode::serialize(msg).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?);
+    Ok(paket)
+}
+
+fn razpakiraj(paket: &[u8]) -> Option<Discovery> {
+    let data = paket.strip_prefix(MAGIC)?;
+    bincode::deserialize(data).ok()
+}
+
+/// odgovor je daljsi od poizvedbe, zato javnim naslovom ne odgovarjamo,
+/// da streznika ni mogoce zlorabiti za ojacanje napada s ponarejenim naslovom
+fn lokalen_naslov(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_loopback(),
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// streznikov del: odgovarja na poizvedbe odjemalcev
+pub struct Oglasevalec {
+    socket: UdpSocket,
+}
+
+impl Oglasevalec {
+    pub fn bind() -> io::Result<Oglasevalec> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+        Ok(Oglasevalec { socket })
+    }
+
+    pub fn odgovori(&mut self, oglas: &OglasStreznika) {
+        let mut buf = [0u8; MAX_PAKET];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, addr)) => {
+                    if !lokalen_naslov(addr.ip()) || !matches!(razpakiraj(&buf[..n]), Some(Discovery::Poizvedba)) {
+                        continue;
+                    }
+                    let odgovor = zapakiraj(&Discovery::Oglas(oglas.clone()));
+                    if let Err(e) = odgovor.and_then(|p| self.socket.send_to(&p, addr)) {
+                        eprintln!("err discovery reply to {}: {}", addr, e);
+                    }
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::ConnectionReset || e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("err discovery recv: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+pub struct NajdenStreznik {
+    /// naslov za povezavo, ip od odgovora in port iz oglasa
+    pub addr: SocketAddr,
+    pub oglas: OglasStreznika,
+    zadnjic: Instant,
+}
+
+/// odjemalcev del: isce streznike z broadcastom
+pub struct Iskalec {
+    socket: UdpSocket,
+    naslednje_iskanje: Instant,
+    strezniki: Vec<NajdenStreznik>,
+}
+
+impl Iskalec {
+    pub fn new() -> io::Result<Iskalec> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Iskalec {
+            socket,
+            naslednje_iskanje: Instant::now(),
+            strezniki: Vec::new(),
+        })
+    }
+
+    pub fn posodobi(&mut self) {
+        if Instant::now() >= self.naslednje_iskanje {
+            self.naslednje_iskanje = Instant::now() + INTERVAL_ISKANJA;
+            let poizvedba = zapakiraj(&Discovery::Poizvedba);
+            if let Err(e) = poizvedba.and_then(|p| self.socket.send_to(&p, (Ipv4Addr::BROADCAST, DISCOVERY_PORT))) {
+                eprintln!("err discovery broadcast: {}", e);
+            }
+        }
+
+        let mut buf = [0u8; MAX_PAKET];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, addr)) => {
+                    let Some(Discovery::Oglas(oglas)) = razpakiraj(&buf[..n]) else { continue };
+                    let addr = SocketAddr::new(addr.ip(), oglas.port);
+                    match self.strezniki.iter_mut().find(|s| s.addr == addr) {
+                        Some(s) => {
+                            s.oglas = oglas;
+                            s.zadnjic = Instant::now();
+                        },
+                        None => self.strezniki.push(NajdenStreznik { addr, oglas, zadnjic: Instant::now() }),
+                    }
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::ConnectionReset || e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("err discovery recv: {}", e);
+                    break;
+                }
+            }
+        }
+
+        self.strezniki.retain(|s| s.zadnjic.elapsed() < POZABI_PO);
+    }
+
+    pub fn strezniki(&self) -> &[NajdenStreznik] {
+        &self.strezniki
+    }
+}
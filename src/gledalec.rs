@@ -0,0 +1,64 @@
+use macroquad::prelude::*;
+
+use crate::{lerp, screen_units_width, screen_units_height, Client, KAMERA_POS};
+
+/// hitrost proste kamere v enotah na sekundo
+const HITROST_KAMERE: f32 = 200.0;
+/// sredina igralca glede na njegovo pozicijo
+const SREDINA_IGRALCA: Vec2 = vec2(8.0, 14.0);
+
+/// kamera gledalca, ki prosto leti ali sledi izbranemu igralcu
+#[derive(Default)]
+pub struct Gledalec {
+    sledi: Option<u32>,
+}
+
+impl Gledalec {
+    /// Q in E zamenjata igralca, WASD premika prosto kamero
+    pub fn posodobi(&mut self, delta: f32, client: &Client) {
+        let igralci = client.vidni_igralci();
+        if is_key_pressed(KeyCode::E) {
+            self.sledi = match self.sledi {
+                Some(id) => igralci.iter().find(|i| **i > id).or(igralci.first()).copied(),
+                None => igralci.first().copied(),
+            };
+        }
+        if is_key_pressed(KeyCode::Q) {
+            self.sledi = match self.sledi {
+                Some(id) => igralci.iter().rev().find(|i| **i < id).or(igralci.last()).copied(),
+                None => igralci.last().copied(),
+            };
+        }
+
+        let mut premik = Vec2::ZERO;
+        if is_key_down(KeyCode::A) { premik.x -= 1.0; }
+        if is_key_down(KeyCode::D) { premik.x += 1.0; }
+        if is_key_down(KeyCode::W) { premik.y -= 1.0; }
+        if is_key_down(KeyCode::S) { premik.y += 1.0; }
+
+        let kamera = KAMERA_POS.get();
+        if premik != Vec2::ZERO {
+            self.sledi = None;
+            KAMERA_POS.set(kamera + premik.normalize() * HITROST_KAMERE * delta);
+        } else if let Some(pozicija) = self.sledi.and_then(|id| client.pozicija_igralca(id)) {
+            // mrtev igralec ni viden, kamera pocaka na mestu, dokler ne ozivi
+            let cilj = pozicija + SREDINA_IGRALCA;
+            KAMERA_POS.set(vec2(lerp(kamera.x, cilj.x, 10.0 * delta), lerp(kamera.y, cilj.y, 10.0 * delta)));
+        }
+    }
+
+    pub fn narisi(&self, client: &Client) {
+        let napis = match self.sledi {
+            Some(id) => format!("SPECTATING {}", client.ime_igralca(id)),
+            None => "FREE CAMERA".to_string(),
+        };
+        let pos = vec2(-screen_units_width() + 3.0, -screen_units_height() + 22.0) + KAMERA_POS.get();
+        for (i, vrstica) in [napis.as_str(), "Q/E: switch player, WASD: move camera"].iter().enumerate() {
+            draw_text_ex(vrstica, pos.x, pos.y + i as f32 * 9.0, TextParams {
+                font_size: 32,
+                font_scale: 0.35,
+                ..Default::default()
+            });
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms, clippy::too_many_arguments)]
+
+pub mod player;
+pub mod collision;
+pub mod network;
+pub mod particles;
+pub mod other;
+pub mod transport;
+pub mod snapshot;
+pub mod discovery;
+pub mod cli;
+pub mod dedicated;
+pub mod chat;
+pub mod admin;
+pub mod gledalec;
+
+pub use player::*;
+pub use collision::*;
+pub use network::*;
+pub use other::*;
+pub use transport::*;
@@ -1,112 +1,256 @@
 use macroquad::prelude::*;
-use std::env;
-
-mod player;
-mod collision;
-mod network;
-mod particles;
-mod other;
-
-use player::*;
-use collision::*;
-use network::*;
-use other::*;
-
-fn print_usage_exit(first_arg: &str) -> ! {
-    eprintln!("ERROR incorect parameters!");
-    eprintln!("usage: {} <user_name> <server_ip>", first_arg);
-    eprintln!("or   : {} <user_name> host", first_arg);
-    std::process::exit(1)
-}
+use macroquad::window::Conf;
 
-#[macroquad::main("VegovciMultiplayer")]
-async fn main() {
-    println!("pozdravljen svet!");
+use vegovci_multiplayer::*;
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        print_usage_exit(&args[0]);
-    }
-    let user_name = &args[1];
-    let server_ip = &args[2];
-    let is_host = args[2] == "host";
+/// prikazuje streznike v lokalnem omrezju, dokler igralec enega ne izbere
+async fn izberi_streznik() -> (String, u16) {
+    let mut iskalec = loop {
+        match discovery::Iskalec::new() {
+            Ok(iskalec) => break iskalec,
+            Err(e) => {
+                let sporocilo = format!("{} (press R to retry)", e);
+                while !is_key_pressed(KeyCode::R) {
+                    narisi_obvestilo("LAN SEARCH FAILED", &sporocilo);
+                    next_frame().await;
+                }
+            }
+        }
+    };
+    let mut izbran: usize = 0;
+
+    loop {
+        iskalec.posodobi();
+        let strezniki = iskalec.strezniki();
 
-    // dodatni argumenti
-    for i in 3..args.len() {
-        match args[i].as_str() {
-            "--colliders" => SHOW_COLLIDERS.set(true),
-            _ => panic!("unknown option: {}", args[i])
+        if is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::W) {
+            izbran = izbran.saturating_sub(1);
         }
+        if is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::S) {
+            izbran += 1;
+        }
+        izbran = izbran.min(strezniki.len().saturating_sub(1));
+
+        if is_key_pressed(KeyCode::Enter) {
+            if let Some(s) = strezniki.get(izbran).filter(|s| s.oglas.zdruzljiv()) {
+                return (s.addr.ip().to_string(), s.addr.port());
+            }
+        }
+
+        let mut vrstice: Vec<String> = strezniki.iter()
+            .map(|s| {
+                let verzija = if s.oglas.zdruzljiv() { "" } else { " [incompatible]" };
+                let geslo = if s.oglas.geslo { " [password]" } else { "" };
+                format!("{} - {} - {}/{} players - {}{}{}", s.oglas.ime, s.oglas.mapa, s.oglas.igralci, s.oglas.max_igralcev, s.addr, geslo, verzija)
+            })
+            .collect();
+        if vrstice.is_empty() {
+            vrstice.push("searching...".to_string());
+        }
+        narisi_seznam("LAN GAMES", &vrstice, izbran);
+        next_frame().await;
+    }
+}
+
+fn main() {
+    let nastavitve = cli::razcleni(std::env::args().skip(1)).unwrap_or_else(|e| cli::izpisi_in_koncaj(e));
+    println!("pozdravljen svet!");
+    if let cli::Ukaz::Server = nastavitve.ukaz {
+        dedicated::zazeni(&nastavitve);
     }
 
+    let conf = Conf {
+        window_title: "VegovciMultiplayer".to_string(),
+        window_width: nastavitve.sirina,
+        window_height: nastavitve.visina,
+        ..Default::default()
+    };
+    macroquad::Window::from_config(conf, igra(nastavitve));
+}
+
+async fn igra(nastavitve: cli::Nastavitve) {
+    let user_name = &nastavitve.ime;
+    let is_host = matches!(nastavitve.ukaz, cli::Ukaz::Host);
+    let bind = std::net::SocketAddr::new(nastavitve.bind, nastavitve.port);
+    let tick_time = nastavitve.tick_time();
+    SHOW_COLLIDERS.set(nastavitve.colliders);
+
     if is_host {
         println!("v nacinu streznika!");
     }
 
-    let mut net_interface = {
-        if is_host { NetInterface::Server(Server::new(user_name.clone())) }
-        else { NetInterface::Client(Client::new(&server_ip, user_name.clone())) }
+    let (server_ip, port) = match nastavitve.ukaz {
+        cli::Ukaz::Join(Some(ref naslov)) => razcleni_naslov(naslov).unwrap_or_else(|| {
+            cli::izpisi_in_koncaj(cli::CliNapaka::Napaka(format!("invalid address: {}", naslov)))
+        }),
+        cli::Ukaz::Join(None) => izberi_streznik().await,
+        _ => (String::new(), nastavitve.port),
+    };
+
+    let povezi = || -> Result<NetInterface, NetError> {
+        if is_host {
+            let (server, client) = Server::new_host(user_name.clone(), bind)?;
+            Ok(NetInterface::Server(Box::new(server), client))
+        }
+        else { Ok(NetInterface::Client(Client::new(&server_ip, port, user_name.clone(), nastavitve.geslo.clone(), nastavitve.gledalec, nastavitve.tcp)?)) }
+    };
+
+    let mut net_interface = loop {
+        match povezi() {
+            Ok(net_interface) => break net_interface,
+            Err(e) => {
+                eprintln!("ERROR {}", e);
+                let sporocilo = format!("{} (press R to retry)", e);
+                while !is_key_pressed(KeyCode::R) {
+                    narisi_obvestilo("CONNECTION FAILED", &sporocilo);
+                    next_frame().await;
+                }
+            }
+        }
     };
 
+    net_interface.client().interpolacija = nastavitve.interpolacija;
+    net_interface.client().timeout = nastavitve.timeout;
+    // vhodi odjemalca privzeto gredo vsak tick
+    net_interface.client().hitrost_posiljanja = nastavitve.send_rate.unwrap_or(nastavitve.tick_rate);
+    if let NetInterface::Server(ref mut server, _) = net_interface {
+        server.timeout = nastavitve.timeout;
+        server.max_rewind = nastavitve.max_rewind;
+        server.mapa = nastavitve.ime_mape();
+        server.nalozi_bane(nastavitve.bani.clone().into());
+        server.geslo = nastavitve.geslo.clone();
+        server.max_igralcev = nastavitve.max_igralcev;
+        if let Some(hz) = nastavitve.send_rate {
+            server.hitrost_posiljanja = hz;
+        }
+    }
+
     let vegovec_texture = load_texture_nearest("assets/vegovec.png").await.unwrap();
-    let map_texture = load_texture_nearest("assets/map.png").await.unwrap();
+    let mut map_texture = load_texture_nearest(&nastavitve.mapa).await.unwrap_or_else(|e| {
+        eprintln!("ERROR could not load map {}: {}", nastavitve.mapa, e);
+        std::process::exit(1);
+    });
 
     physics::init();
     particles::init();
 
-    let _map_aabb_refs = generate_map_colliders(map_texture.get_texture_data(), vec2(-256.0, -128.0));
+    let mut trenutna_mapa = nastavitve.ime_mape();
+    let mut map_aabb_refs = generate_map_colliders(map_texture.get_texture_data(), MAP_POS);
     //map_aabb_refs.push(physics::dodaj_staticen_obj(AABB::new(-96.0, 48.0, 192.0, 32.0)));
     //map_aabb_refs.push(physics::dodaj_staticen_obj(AABB::new(32.0, 16.0, 16.0, 32.0)));
 
     //let _test_aabb = physics::dodaj_dinamicen_obj(AABB::new(-32.0, 16.0, 16.0, 32.0), LAYER_MAP, LAYER_MAP | LAYER_PLAYER | LAYER_SWORD, 0);
 
-    let mut player = Player::new(user_name.clone(), vec2(0.0, 0.0), vegovec_texture);
+    // gledalec nima svojega igralca
+    let mut player = (!nastavitve.gledalec).then(|| Player::new(user_name.clone(), vec2(0.0, 0.0), vegovec_texture.clone()));
+    let mut gledalec = nastavitve.gledalec.then(gledalec::Gledalec::default);
 
     println!("stevilo staticnih objektov: {}", physics::st_staticnih_obj());
     println!("stevilo dinamicnih objektov: {}", physics::st_dinamicnih_obj());
 
     let mut leaderboard_data = Vec::new();
+    let mut klepet = chat::Klepet::default();
+    // simulacija tece s fiksnim korakom, ne glede na hitrost risanja
+    let mut akumulator = 0.0;
 
     loop {
-        let delta = get_frame_time().min(1.0 / 15.0);
-
-        match net_interface {
-            NetInterface::Server(ref mut server) => {
-                server.listen();
-                server.recv();
-                server.posodobi(delta, &mut player);
-                player.health = server.health;
-                if player.attack_time == 0.0 {
-                    server.attack_host(&player);
+        let delta = get_frame_time().min(MAX_DELTA);
+        akumulator += delta;
+
+        if let Some(tekst) = klepet.posodobi() {
+            net_interface.client().poslji_chat(&tekst);
+        }
+        if let Some(player) = &mut player {
+            player.vhod_onemogocen = klepet.odprt();
+            player.zabelezi_pritiske();
+        }
+
+        if let Some(ime) = net_interface.client().nova_mapa.take().filter(|ime| *ime != trenutna_mapa) {
+            let tekstura = match pot_mape(&ime) {
+                Some(pot) => load_texture_nearest(&pot).await.map_err(|e| e.to_string()),
+                None => Err("invalid map name".to_string()),
+            };
+            match tekstura {
+                Ok(tekstura) => {
+                    map_aabb_refs.clear();
+                    map_aabb_refs = generate_map_colliders(tekstura.get_texture_data(), MAP_POS);
+                    map_texture = tekstura;
+                    trenutna_mapa = ime;
+                },
+                Err(e) => eprintln!("err could not load map {}: {}", ime, e),
+            }
+        }
+
+        while akumulator >= tick_time {
+            akumulator -= tick_time;
+
+            if let NetInterface::Server(ref mut server, _) = net_interface {
+                server.tick(tick_time);
+                // pri gostitelju collidere mape zamenja odjemalec, ko dobi Mapa
+                server.nova_mapa = None;
+            }
+
+            let client = net_interface.client();
+            client.recv();
+            if let Some(player) = &mut player {
+                if let Some(pozicija) = client.respawn.take() {
+                    player.nastavi_pozicijo(pozicija);
                 }
-                server.poslji_vse_state(&player);
-            },
-            NetInterface::Client(ref mut client) => {
-                client.recv(&mut player);
                 player.health = client.health;
-                let state = State {
-                    id: client.id,
-                    position: (player.position.x, player.position.y),
-                    rotation: player.rotation,
-                    anim_frame: player.animacije[player.trenutna_anim].izr_frame_xy().into(),
-                    attack_time: player.attack_time,
-                    razdalja_meca: player.razdalja_meca,
-                };
-                let send_buf = bincode::serialize(&Message::PlayerState(state)).unwrap();
-                client.send(&send_buf);
-            },
-        }
+                if player.ime != client.ime() {
+                    player.ime = client.ime().to_string();
+                }
+                if let Some(state) = client.potrjeno_stanje.take() {
+                    player.uskladi(state.position.into(), state.attack_time, &state.gibanje, client.nepotrjeni_vhodi());
+                }
+                if let Some(vhod) = player.posodobi(tick_time) {
+                    if client.status == ClientStatus::Povezan {
+                        client.poslji_vhod(vhod);
+                    }
+                }
+            } else if client.status == ClientStatus::Povezan {
+                client.poslji_potrditev(tick_time);
+            }
 
-        player.posodobi(delta);
+            // trki lokalnega igralca, streznik je svoje ze razresil
+            physics::resi_trke();
+            physics::resi_trke();
+        }
 
-        physics::resi_trke();
-        physics::resi_trke();
+        match net_interface.client().status {
+            ClientStatus::Zavrnjen(ref reason) => {
+                narisi_obvestilo("CONNECTION REJECTED", reason);
+                next_frame().await;
+                continue;
+            },
+            ClientStatus::VCakalniVrsti(pozicija) => {
+                narisi_obvestilo("SERVER FULL", &format!("waiting for a free slot, position {} in queue", pozicija));
+                next_frame().await;
+                continue;
+            },
+            ClientStatus::Prekinjen(ref reason) => {
+                narisi_obvestilo("DISCONNECTED", reason);
+                next_frame().await;
+                continue;
+            },
+            _ => {},
+        }
 
+        if let Some(player) = &player {
+            player.premakni_kamero(delta);
+        }
+        if let Some(gledalec) = &mut gledalec {
+            if !klepet.odprt() {
+                gledalec.posodobi(delta, net_interface.client());
+            }
+        }
         posodobi_kamero();
         clear_background(Color::new(0.1, 0.1, 0.1, 1.0));
 
-        draw_texture(&map_texture, -256.0, -128.0, WHITE);
-        player.narisi();
+        draw_texture(&map_texture, MAP_POS.x, MAP_POS.y, WHITE);
+        if let Some(player) = &player {
+            player.narisi();
+        }
 
         particles::narisi(delta);
 
@@ -114,13 +258,9 @@ async fn main() {
             physics::narisi_aabbje();
         }
 
-        match net_interface {
-            NetInterface::Server(ref server) => {
-                server.narisi_cliente(&player.texture);
-            }
-            NetInterface::Client(ref client) => {
-                client.narisi_cliente(&player.texture);
-            }
+        net_interface.client().narisi_cliente(&vegovec_texture);
+        if let Some(gledalec) = &gledalec {
+            gledalec.narisi(net_interface.client());
         }
 
         let pos = vec2(-screen_units_width() + 3.0, -screen_units_height() + 11.0) + KAMERA_POS.get();
@@ -131,21 +271,17 @@ async fn main() {
         });
 
         narisi_pop_up_messages(delta);
+        klepet.narisi();
+
+        if let Some(poskus) = net_interface.client().ponovno_povezovanje() {
+            narisi_prekrivalo("RECONNECTING", &format!("attempt {}", poskus));
+        }
 
         if is_key_down(KeyCode::Tab) {
-            match net_interface {
-                NetInterface::Server(ref mut server) => {
-                    if server.nov_leaderboard {
-                        server.nov_leaderboard = false;
-                        leaderboard_data = server.get_leaderboard_data();
-                    }
-                },
-                NetInterface::Client(ref mut client) => {
-                    if client.nov_leaderboard {
-                        client.nov_leaderboard = false;
-                        leaderboard_data = client.get_leaderboard_data();
-                    }
-                }
+            let client = net_interface.client();
+            if client.nov_leaderboard {
+                client.nov_leaderboard = false;
+                leaderboard_data = client.get_leaderboard_data();
             }
             narisi_leaderboard(&leaderboard_data);
         }
@@ -1,134 +1,667 @@
-use std::{collections::HashMap, io::{ErrorKind, Write, BufReader}, net::{TcpStream, TcpListener, SocketAddr}};
+use std::{collections::{HashMap, VecDeque, hash_map::RandomState}, hash::BuildHasher, io, net::{IpAddr, SocketAddr}, path::{Path, PathBuf}, time::{Duration, Instant}};
 use macroquad::prelude::*;
 use serde::{Serialize, Deserialize};
-use crate::{Player, DinamicenAABBRef, physics, LAYER_PLAYER, particles, particles::HIT_PARTICLES, SHOW_COLLIDERS, pop_up_msg};
-use crate::AABB;
-
-const PORT: u16 = 5356;
-const RESPAWN_TIME: f32 = 3.0;
+use crate::{Player, PlayerInput, Gibanje, StanjeGibanja, MAX_DELTA, MIN_DELTA, TICK_TIME, LAYER_PLAYER, LAYER_SWORD, particles, particles::HIT_PARTICLES, SHOW_COLLIDERS, pop_up_msg, pop_up_msg_time, pot_mape, lerp, lerp_kot};
+use crate::{AABB, physics};
+use crate::admin::{self, AdminUkaz};
+use crate::discovery::{Oglasevalec, OglasStreznika};
+use crate::snapshot::{self, KvantiziranoStanje, ZGODOVINA_POSNETKOV};
+use crate::{Povezava, Poslusalec, LoopbackPoslusalec, RecvError, connect_tcp, connect_udp, poslusaj};
+
+pub const PORT: u16 = 5356;
+/// poveca se ob vsaki nezdruzljivi spremembi sporocil
+pub const PROTOCOL_VERSION: u32 = 14;
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// koliko casa ima nova povezava, da poslje Hello
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+pub const PRIVZETI_RESPAWN_TIME: f32 = 3.0;
 const FALLOFF_Y: f32 = 500.0;
+/// koliko casa vnaprej lahko odjemalec poslje vhode, da ne more pospesiti igre
+const MAX_ZALOGA_VHODOV: f32 = 0.25;
+/// najvec vhodov, ki jih odjemalec poslje v enem sporocilu
+pub const MAX_VHODOV_NA_MSG: usize = 32;
+
+/// za koliko so drugi igralci narisani v preteklosti, da je med posnetki vedno kaj za interpolirat
+pub const PRIVZETA_INTERPOLACIJA: Duration = Duration::from_millis(100);
+/// kako dalec naprej lahko ugibamo pozicijo, ko posnetki zamujajo
+const MAX_EKSTRAPOLACIJA: Duration = Duration::from_millis(250);
+/// vecji premik med posnetkoma je teleport (respawn) in se ne interpolira
+const TELEPORT_RAZDALJA: f32 = 100.0;
+
+/// koliko casa streznik hrani mesto igralca, ki je izgubil povezavo
+const CAS_ZA_VRNITEV: Duration = Duration::from_secs(30);
+/// prvi premor pred ponovnim povezovanjem, vsak naslednji je dvakrat daljsi
+const ZACETNI_PREMOR: Duration = Duration::from_millis(500);
+const MAX_PREMOR: Duration = Duration::from_secs(8);
+
+/// kako pogosto streznik izmeri ping in ga poslje vsem
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// po koliko casa brez sporocil je povezava mrtva
+pub const PRIVZETI_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// kolikokrat na sekundo streznik poslje stanje igralcev
+pub const PRIVZETA_HITROST_POSILJANJA: f32 = 30.0;
+
+/// najvec igralcev hkrati, steti so tudi prekinjeni, ki se lahko vrnejo
+pub const PRIVZETO_MAX_IGRALCEV: usize = 16;
+/// koliko povezav lahko caka na prosto mesto, ko je streznik poln
+const MAX_CAKALNA_VRSTA: usize = 8;
+/// gledalci ne zasedejo mest igralcev, zato so omejeni posebej
+const MAX_GLEDALCEV: usize = 8;
+/// sproscen id se ne uporabi takoj, da ga odjemalci in zgodovina zadetkov ne zamesajo s prejsnjim igralcem
+const KARANTENA_IDJA: Duration = Duration::from_secs(10);
+
+/// po toliko napacnih geslih z istega naslova streznik zavraca vse poskuse
+const MAX_NAPACNIH_GESEL: u32 = 5;
+/// kako dolgo po zadnjem napacnem geslu velja zaklep
+const ZAKLEP_PO_NAPACNIH_GESLIH: Duration = Duration::from_secs(60);
+
+/// najvec, za koliko streznik previje cas pri preverjanju zadetkov
+pub const PRIVZETI_MAX_REWIND: Duration = Duration::from_millis(200);
+
+/// daljsa sporocila v klepetu streznik skrajsa
+pub const MAX_DOLZINA_SPOROCILA: usize = 120;
+/// koliko sporocil lahko igralec poslje naenkrat
+const MAX_ZALOGA_SPOROCIL: f32 = 5.0;
+/// koliko sporocil na sekundo se doda v zalogo
+const SPOROCILA_NA_SEKUNDO: f32 = 1.0;
+/// kako dolgo je sporocilo iz klepeta na zaslonu
+const CAS_SPOROCILA: f32 = 8.0;
+/// posiljatelj sporocil, ki jih napise streznik sam
+pub const ID_STREZNIKA: u32 = u32::MAX;
+
+/// pozicije vseh zivih igralcev ob enem poslanem stanju
+struct ZgodovinaTick {
+    tick: u32,
+    cas: Instant,
+    igralci: Vec<(u32, AABB)>,
+}
 
-fn prepare_socket(stream: &mut TcpStream) {
-    stream.set_nonblocking(true).unwrap();
-    stream.set_nodelay(true).unwrap();
+#[derive(Debug)]
+pub enum NetError {
+    /// poslusanje na portu ni uspelo, npr. ker je port zaseden
+    Bind(SocketAddr, io::Error),
+    Connect(io::Error),
+    Send(io::Error),
+    Recv(RecvError),
+    /// druga stran predolgo ni nic poslala
+    Timeout(Duration),
 }
 
-/// vrne true ce je disconnect
-fn handle_bincode_error(e: Box<bincode::ErrorKind>) -> bool {
-    match *e {
-        bincode::ErrorKind::Io(e) => {
-            match e.kind() {
-                ErrorKind::WouldBlock => {
-                },
-                ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset => {
-                    return true; // disconnect
-                },
-                _ => {
-                    eprintln!("io err recv: {:?}", e);
-                }
-            }
-        },
-        _ => {
-            eprintln!("err recv: {:?}", e);
+impl std::fmt::Display for NetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetError::Bind(addr, e) => write!(f, "could not listen on {}: {}", addr, e),
+            NetError::Connect(e) => write!(f, "could not connect: {}", e),
+            NetError::Send(e) => write!(f, "send failed: {}", e),
+            NetError::Recv(e) => write!(f, "receive failed: {}", e),
+            NetError::Timeout(cas) => write!(f, "timed out after {:.1}s", cas.as_secs_f32()),
         }
     }
+}
 
-    return false;
+impl std::error::Error for NetError {}
+
+/// razcleni "host", "host:port", "ip", "[ipv6]" ali "[ipv6]:port" v naslov in port
+pub fn razcleni_naslov(naslov: &str) -> Option<(String, u16)> {
+    if let Ok(addr) = naslov.parse::<SocketAddr>() {
+        return Some((addr.ip().to_string(), addr.port()));
+    }
+    let brez_oklepajev = naslov.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = brez_oklepajev.parse::<IpAddr>() {
+        return Some((ip.to_string(), PORT));
+    }
+    match naslov.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None if !naslov.is_empty() => Some((naslov.to_string(), PORT)),
+        None => None,
+    }
+}
+
+/// najdaljse dovoljeno ime igralca v znakih
+pub const MAX_DOLZINA_IMENA: usize = 16;
+
+/// ime sme imeti le crke, stevke in znake _-.
+pub fn preveri_ime(ime: &str) -> Result<(), String> {
+    let dolzina = ime.chars().count();
+    if dolzina == 0 || dolzina > MAX_DOLZINA_IMENA {
+        return Err(format!("name must be 1 to {} characters long", MAX_DOLZINA_IMENA));
+    }
+    if let Some(c) = ime.chars().find(|c| !(c.is_alphanumeric() || "_-.".contains(*c))) {
+        return Err(format!("name contains invalid character '{}'", c));
+    }
+    Ok(())
+}
+
+/// odstrani nedovoljene znake, prazno ali predolgo ime je napaka
+pub fn ocisti_ime(ime: &str) -> Result<String, String> {
+    let ime: String = ime.trim().chars().filter(|c| c.is_alphanumeric() || "_-.".contains(*c)).collect();
+    preveri_ime(&ime)?;
+    Ok(ime)
+}
+
+/// odstrani kontrolne znake in presledke na robovih ter skrajsa na MAX_DOLZINA_SPOROCILA
+pub fn ocisti_sporocilo(tekst: &str) -> String {
+    let tekst: String = tekst.chars().filter(|c| !c.is_control()).collect();
+    tekst.trim().chars().take(MAX_DOLZINA_SPOROCILA).collect()
 }
 
 pub struct ServerConnection {
-    reader: BufReader<TcpStream>,
-    state: State,
+    povezava: Box<dyn Povezava>,
+    id: u32,
+    /// s tem se igralec vrne na svoje mesto po prekinitvi
+    token: u64,
     addr: SocketAddr,
     user_name: String,
-    aabb_ref: DinamicenAABBRef,
+    /// gledalec nima gibanja in ni v svetu fizike
+    gibanje: Option<Gibanje>,
+    /// seq zadnjega simuliranega vhoda
+    zadnji_vhod: u32,
+    /// koliko casa simulacije se lahko porabi z vhodi
+    zaloga_vhodov: f32,
+    /// koliko sporocil v klepetu lahko se poslje
+    zaloga_sporocil: f32,
+    /// ze je poslal UserInfo na tej povezavi, nadaljnji so preimenovanja
+    predstavljen: bool,
+    /// zadnji tick, ki ga je odjemalec prejel
+    potrjen_tick: u32,
+    /// poslani posnetki, ki so lahko osnova za razliko
+    poslani_posnetki: VecDeque<(u32, Vec<KvantiziranoStanje>)>,
     health: i32,
     respawn_timer: f32,
     kills: i32,
+    /// napaka pri posiljanju, zaradi katere bo povezava prekinjena
+    napaka: Option<NetError>,
+    zadnji_prejem: Instant,
+    /// seq in cas poslanega ping, na katerega se cakamo odgovor
+    cakajoc_ping: Option<(u32, Instant)>,
+    ping: Duration,
+}
+
+impl ServerConnection {
+    fn gledalec(&self) -> bool {
+        self.gibanje.is_none()
+    }
+}
+
+/// mesto igralca, ki je izgubil povezavo in se lahko vrne s tokenom
+struct PrekinjenaSeja {
+    token: u64,
+    id: u32,
+    user_name: String,
+    kills: i32,
+    health: i32,
+    respawn_timer: f32,
+    pozicija: Vec2,
+    od: Instant,
+}
+
+/// povezava, ki se ni poslala Hello in Prijava
+struct PendingConnection {
+    povezava: Box<dyn Povezava>,
+    addr: SocketAddr,
+    zacetek: Instant,
+    /// verzija je ze preverjena, cakamo na Prijava
+    hello: bool,
+}
+
+/// povezava, ki je bila sprejeta, a je streznik poln
+struct CakajocNaMesto {
+    conn: PendingConnection,
+    token: Option<u64>,
+    zadnji_prejem: Instant,
 }
 
 pub struct Server {
-    listener: TcpListener,
+    poslusalci: Vec<Box<dyn Poslusalec>>,
+    cakajoci: Vec<PendingConnection>,
     clients: Vec<ServerConnection>,
+    prekinjene: Vec<PrekinjenaSeja>,
+    cakalna_vrsta: VecDeque<CakajocNaMesto>,
+    pub max_igralcev: usize,
     naslednji_id: u32,
-    user_name: String,
-    pub health: i32,
-    respawn_timer: f32,
-    kills: i32,
-    pub nov_leaderboard: bool,
+    /// sprosceni id in cas sprostitve
+    prosti_idji: Vec<(u32, Instant)>,
+    /// stevilka zadnjega poslanega posnetka
+    tick: u32,
+    pub hitrost_posiljanja: f32,
+    cas_od_posiljanja: f32,
+    zgodovina: VecDeque<ZgodovinaTick>,
+    pub max_rewind: Duration,
+    pub timeout: Duration,
+    /// ime in mapa, ki ju streznik oglasa v lokalnem omrezju
+    pub ime: String,
+    pub mapa: String,
+    port: u16,
+    oglasevalec: Option<Oglasevalec>,
+    naslednji_ping: Instant,
+    ping_seq: u32,
+    pub respawn_time: f32,
+    bani: Vec<IpAddr>,
+    /// kam se shranijo bani, da ostanejo po ponovnem zagonu
+    datoteka_banov: Option<PathBuf>,
+    /// ukazi gostitelja iz klepeta, izvedejo se po obdelavi vseh sporocil
+    cakajoci_ukazi: Vec<(u32, String)>,
+    /// id in razlog za povezave, ki jih je treba odstraniti po obdelavi vseh sporocil
+    za_odstranitev: Vec<(u32, String)>,
+    /// pot do mape po ukazu /map, collidere zamenja tisti, ki jih je naredil
+    pub nova_mapa: Option<String>,
+    /// brez gesla se lahko poveze vsak
+    pub geslo: Option<String>,
+    /// stevilo napacnih gesel in cas zadnjega za vsak ip, ker se port ob vsakem poskusu spremeni
+    napacna_gesla: HashMap<IpAddr, (u32, Instant)>,
 }
 
 impl Server {
-    pub fn new(user_name: String) -> Server {
-        let listener = TcpListener::bind(("0.0.0.0", PORT)).unwrap();
-        listener.set_nonblocking(true).unwrap();
+    /// streznik brez lokalnega igralca
+    pub fn dedicated(bind: SocketAddr) -> Result<Server, NetError> {
+        let poslusalci = poslusaj(bind).map_err(|e| NetError::Bind(bind, e))?;
+        let mut server = Server::with_listeners(poslusalci);
+        server.port = bind.port();
+        server.oglasuj();
+        Ok(server)
+    }
+
+    /// streznik in odjemalec za igralca, ki gosti igro,
+    /// povezana preko loopback, da je gostitelj obravnavan enako kot ostali
+    pub fn new_host(user_name: String, bind: SocketAddr) -> Result<(Server, Client), NetError> {
+        let (lokalni, connector) = LoopbackPoslusalec::new();
+        let mut poslusalci = poslusaj(bind).map_err(|e| NetError::Bind(bind, e))?;
+        poslusalci.push(Box::new(lokalni));
+
+        let mut server = Server::with_listeners(poslusalci);
+        server.port = bind.port();
+        server.ime = format!("{}'s game", user_name);
+        server.oglasuj();
+        let client = Client::with_connection(connector.connect().map_err(NetError::Connect)?, user_name, None, false);
+        Ok((server, client))
+    }
+
+    /// brez oglasevanja se se vedno lahko povezes z ip naslovom,
+    /// npr. ko na istem racunalniku tece vec streznikov in je port za iskanje ze zaseden
+    fn oglasuj(&mut self) {
+        match Oglasevalec::bind() {
+            Ok(o) => self.oglasevalec = Some(o),
+            Err(e) => eprintln!("err lan discovery disabled: {}", e),
+        }
+    }
+
+    pub fn with_listeners(poslusalci: Vec<Box<dyn Poslusalec>>) -> Server {
         Server {
-            listener,
+            poslusalci,
+            cakajoci: Vec::new(),
             clients: Vec::new(),
+            prekinjene: Vec::new(),
+            cakalna_vrsta: VecDeque::new(),
+            max_igralcev: PRIVZETO_MAX_IGRALCEV,
             naslednji_id: 1,
-            user_name,
-            health: 100,
-            respawn_timer: 0.0,
-            kills: 0,
-            nov_leaderboard: true,
+            prosti_idji: Vec::new(),
+            tick: 0,
+            hitrost_posiljanja: PRIVZETA_HITROST_POSILJANJA,
+            cas_od_posiljanja: 0.0,
+            zgodovina: VecDeque::new(),
+            max_rewind: PRIVZETI_MAX_REWIND,
+            timeout: PRIVZETI_TIMEOUT,
+            ime: "vegovci server".to_string(),
+            mapa: "map".to_string(),
+            port: PORT,
+            oglasevalec: None,
+            naslednji_ping: Instant::now(),
+            ping_seq: 0,
+            respawn_time: PRIVZETI_RESPAWN_TIME,
+            bani: Vec::new(),
+            datoteka_banov: None,
+            cakajoci_ukazi: Vec::new(),
+            za_odstranitev: Vec::new(),
+            nova_mapa: None,
+            geslo: None,
+            napacna_gesla: HashMap::new(),
+        }
+    }
+
+    fn on_start_conn(&self, povezava: &mut Box<dyn Povezava>, id: u32, token: u64) {
+        let mut msgs = vec![Message::Welcome { id, token }, Message::Mapa(self.mapa.clone())];
+
+        // ostali dobijo UserInfo, ko se novi predstavi
+        for client in self.clients.iter().filter(|c| !c.gledalec() && c.predstavljen) {
+            msgs.push(Message::UserInfo((client.id, client.user_name.clone(), client.kills)));
+        }
+        for seja in &self.prekinjene {
+            msgs.push(Message::UserInfo((seja.id, seja.user_name.clone(), seja.kills)));
+        }
+
+        for msg in &msgs {
+            if let Err(e) = povezava.send(msg) {
+                eprintln!("err socket write: {:?}", e);
+            }
         }
     }
 
-    fn on_start_conn(&self, stream: &mut TcpStream) {
-        prepare_socket(stream);
+    fn reject(conn: &mut PendingConnection, reason: String) {
+        println!("rejected client {}: {}", conn.addr, reason);
+        let msg = Message::Reject { reason };
+        if let Err(e) = conn.povezava.send(&msg) {
+            eprintln!("err socket write: {:?}", e);
+        }
+        conn.povezava.disconnect();
+    }
 
-        let mut send_buf: Vec<u8> = Vec::new();
-        send_buf.append(&mut bincode::serialize(&Message::DodeljenId(self.naslednji_id)).unwrap());
+    /// preveri Hello in vrne razlog za zavrnitev, ce verzija ni zdruzljiva
+    fn preveri_hello(protocol_version: u32, game_version: &str) -> Option<String> {
+        if protocol_version != PROTOCOL_VERSION {
+            return Some(format!("incompatible protocol version (server {}, client {})", PROTOCOL_VERSION, protocol_version));
+        }
+        if game_version != GAME_VERSION {
+            return Some(format!("incompatible game version (server {}, client {})", GAME_VERSION, game_version));
+        }
+        None
+    }
 
-        let msg = Message::UserInfo((0, self.user_name.clone(), self.kills));
-        send_buf.append(&mut bincode::serialize(&msg).unwrap());
+    /// gostitelj geslo ze pozna, zato ga lokalna povezava ne potrebuje
+    fn preveri_geslo(&mut self, conn: &PendingConnection, geslo: Option<&str>) -> Option<String> {
+        if self.geslo.is_none() || conn.povezava.lokalna() {
+            return None;
+        }
+        let ip = conn.addr.ip();
+        if self.napacna_gesla.get(&ip).is_some_and(|(poskusi, _)| *poskusi >= MAX_NAPACNIH_GESEL) {
+            return Some("too many wrong passwords, try again later".to_string());
+        }
+        if geslo == self.geslo.as_deref() {
+            self.napacna_gesla.remove(&ip);
+            return None;
+        }
 
-        for client in &self.clients {
-            let msg = Message::UserInfo((client.state.id, client.user_name.clone(), client.kills));
-            send_buf.append(&mut bincode::serialize(&msg).unwrap());
+        let (poskusi, zadnji) = self.napacna_gesla.entry(ip).or_insert((0, Instant::now()));
+        *poskusi += 1;
+        *zadnji = Instant::now();
+        match geslo {
+            None => Some("this server requires a password".to_string()),
+            Some(_) => Some("wrong password".to_string()),
         }
+    }
 
-        stream.write(&send_buf).unwrap();
+    /// najmanjsi id, ki je bil sproscen pred vec kot KARANTENA_IDJA, sicer nov
+    fn nov_id(&mut self) -> u32 {
+        let prost = self.prosti_idji.iter()
+            .enumerate()
+            .filter(|(_, (_, od))| od.elapsed() > KARANTENA_IDJA)
+            .min_by_key(|(_, (id, _))| *id)
+            .map(|(i, _)| i);
+        if let Some(i) = prost {
+            return self.prosti_idji.swap_remove(i).0;
+        }
+        self.naslednji_id += 1;
+        self.naslednji_id - 1
     }
 
-    pub fn listen(&mut self) {
-        for conn_attempt in self.listener.incoming() {
-            match conn_attempt {
-                Ok(mut stream) => {
-                    let addr = stream.peer_addr().unwrap();
-                    println!("new client connected from {}", addr);
-                    self.on_start_conn(&mut stream);
-                    self.clients.push(ServerConnection {
-                        reader: BufReader::new(stream),
-                        state: State {
-                            id: self.naslednji_id,
-                            ..Default::default()
-                        },
-                        addr,
-                        user_name: String::new(),
-                        aabb_ref: physics::dodaj_dinamicen_obj(AABB::new(0.0, 0.0, 16.0, 28.0), LAYER_PLAYER, 0, self.naslednji_id),
-                        health: 100,
-                        respawn_timer: 0.0,
-                        kills: 0,
-                    });
-                    self.naslednji_id += 1;
-                    self.nov_leaderboard = true;
-                },
-                Err(e) => {
-                    if e.kind() == ErrorKind::WouldBlock {
+    fn sprosti_id(&mut self, id: u32) {
+        self.prosti_idji.push((id, Instant::now()));
+    }
+
+    fn poln(&self) -> bool {
+        self.st_igralcev() + self.prekinjene.len() >= self.max_igralcev
+    }
+
+    fn st_igralcev(&self) -> usize {
+        self.clients.iter().filter(|c| !c.gledalec()).count()
+    }
+
+    /// igralec s tokenom ima mesto ze rezervirano
+    fn ima_mesto(&self, token: Option<u64>) -> bool {
+        let Some(token) = token else { return false };
+        self.clients.iter().any(|c| c.token == token) || self.prekinjene.iter().any(|s| s.token == token)
+    }
+
+    fn postavi_v_vrsto(&mut self, mut conn: PendingConnection, token: Option<u64>) {
+        if self.cakalna_vrsta.len() >= MAX_CAKALNA_VRSTA {
+            Server::reject(&mut conn, "server is full".to_string());
+            return;
+        }
+        println!("server full, client {} is waiting in queue", conn.addr);
+        let pozicija = self.cakalna_vrsta.len() as u32 + 1;
+        if let Err(e) = conn.povezava.send(&Message::CakalnaVrsta(pozicija)) {
+            eprintln!("err socket write: {:?}", e);
+        }
+        self.cakalna_vrsta.push_back(CakajocNaMesto { conn, token, zadnji_prejem: Instant::now() });
+    }
+
+    /// sprejme cakajoce, ko se sprosti mesto, in odstrani tiste, ki so odsli
+    fn posodobi_cakalno_vrsto(&mut self) {
+        let mut spremenjena = false;
+        while !self.poln() {
+            let Some(cakajoc) = self.cakalna_vrsta.pop_front() else { break };
+            self.sprejmi(cakajoc.conn, cakajoc.token, false);
+            spremenjena = true;
+        }
+
+        let mut i = 0;
+        while i < self.cakalna_vrsta.len() {
+            let cakajoc = &mut self.cakalna_vrsta[i];
+            // odjemalec v vrsti odgovarja le na ping
+            let mut ziv = cakajoc.conn.povezava.preberi().is_ok();
+            loop {
+                match cakajoc.conn.povezava.naslednji_msg() {
+                    Ok(Some(_)) => cakajoc.zadnji_prejem = Instant::now(),
+                    Ok(None) => break,
+                    Err(_) => {
+                        ziv = false;
                         break;
                     }
-                    eprintln!("error in TcpListener incoming: {}", e);
                 }
             }
+            if !ziv || cakajoc.zadnji_prejem.elapsed() > self.timeout {
+                println!("client {} left the queue", cakajoc.conn.addr);
+                self.cakalna_vrsta.remove(i);
+                spremenjena = true;
+                continue;
+            }
+            i += 1;
+        }
+
+        if spremenjena {
+            self.poslji_pozicije_v_vrsti();
         }
     }
 
-    fn najdi_ime_za_id(&self, id: u32) -> &str {
-        if id == 0 {
-            return &self.user_name;
+    fn poslji_pozicije_v_vrsti(&mut self) {
+        for (i, cakajoc) in self.cakalna_vrsta.iter_mut().enumerate() {
+            if let Err(e) = cakajoc.conn.povezava.send(&Message::CakalnaVrsta(i as u32 + 1)) {
+                eprintln!("err socket write: {:?}", e);
+            }
+        }
+    }
+
+    fn je_ban(&self, conn: &PendingConnection) -> bool {
+        self.bani.contains(&conn.addr.ip()) && !conn.povezava.lokalna()
+    }
+
+    /// zavrne povezave z ip, ki se niso sprejete
+    fn zavrni_bane(&mut self) {
+        let (bani, ostali): (Vec<_>, Vec<_>) = std::mem::take(&mut self.cakajoci).into_iter().partition(|c| self.je_ban(c));
+        self.cakajoci = ostali;
+        for mut conn in bani {
+            Server::reject(&mut conn, "you are banned from this server".to_string());
+        }
+
+        let st = self.cakalna_vrsta.len();
+        let (bani, ostali): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.cakalna_vrsta).into_iter().partition(|c| self.je_ban(&c.conn));
+        self.cakalna_vrsta = ostali;
+        for mut cakajoc in bani {
+            Server::reject(&mut cakajoc.conn, "you are banned from this server".to_string());
+        }
+        if self.cakalna_vrsta.len() != st {
+            self.poslji_pozicije_v_vrsti();
+        }
+    }
+
+    fn nov_token(id: u32) -> u64 {
+        // RandomState je nakljucno inicializiran, zato je token tezko uganiti
+        RandomState::new().hash_one((id, Instant::now()))
+    }
+
+    fn sprejmi(&mut self, mut conn: PendingConnection, token: Option<u64>, gledalec: bool) {
+        // ip je bil morda bannan, medtem ko je povezava cakala
+        if self.je_ban(&conn) {
+            Server::reject(&mut conn, "you are banned from this server".to_string());
+            return;
+        }
+        let PendingConnection { mut povezava, addr, .. } = conn;
+
+        // stara povezava istega igralca morda se ni opazila prekinitve
+        if let Some(i) = token.and_then(|t| self.clients.iter().position(|c| c.token == t)) {
+            self.prekini(i);
+        }
+        let seja = token
+            .filter(|_| !gledalec)
+            .and_then(|t| self.prekinjene.iter().position(|s| s.token == t))
+            .map(|i| self.prekinjene.swap_remove(i));
+
+        let (id, token) = match &seja {
+            Some(seja) => (seja.id, seja.token),
+            None => {
+                let id = self.nov_id();
+                (id, Server::nov_token(id))
+            }
+        };
+
+        if gledalec {
+            println!("spectator {} accepted with id {}", addr, id);
+        } else {
+            println!("client {} accepted with id {}", addr, id);
+        }
+        self.on_start_conn(&mut povezava, id, token);
+        let mut conn = ServerConnection {
+            povezava,
+            id,
+            token,
+            addr,
+            // gledalec ne poslje UserInfo
+            user_name: if gledalec { "spectator".to_string() } else { String::new() },
+            gibanje: (!gledalec).then(|| Gibanje::new(Vec2::ZERO, LAYER_PLAYER, LAYER_SWORD, id)),
+            zadnji_vhod: 0,
+            zaloga_vhodov: MAX_ZALOGA_VHODOV,
+            zaloga_sporocil: MAX_ZALOGA_SPOROCIL,
+            predstavljen: false,
+            potrjen_tick: 0,
+            poslani_posnetki: VecDeque::new(),
+            health: 100,
+            respawn_timer: 0.0,
+            kills: 0,
+            napaka: None,
+            zadnji_prejem: Instant::now(),
+            cakajoc_ping: None,
+            ping: Duration::ZERO,
+        };
+
+        if let Some(seja) = seja {
+            println!("{} resumed", seja.user_name);
+            conn.user_name = seja.user_name;
+            conn.kills = seja.kills;
+            conn.health = seja.health;
+            conn.respawn_timer = seja.respawn_timer;
+            if let Some(gibanje) = &mut conn.gibanje {
+                gibanje.nastavi_pozicijo(seja.pozicija);
+            }
+        }
+        self.clients.push(conn);
+    }
+
+    fn obdelaj_cakajoce(&mut self) {
+        let mut i = 0;
+        while i < self.cakajoci.len() {
+            let conn = &mut self.cakajoci[i];
+            let prebrano = conn.povezava.preberi();
+
+            match conn.povezava.naslednji_msg() {
+                Ok(Some(Message::Hello { protocol_version, game_version })) if !conn.hello => {
+                    if let Some(reason) = Server::preveri_hello(protocol_version, &game_version) {
+                        let mut conn = self.cakajoci.swap_remove(i);
+                        Server::reject(&mut conn, reason);
+                        continue;
+                    }
+                    // Prijava je morda prispela v istem paketu
+                    conn.hello = true;
+                    continue;
+                },
+                Ok(Some(Message::Prijava { token, geslo, gledalec })) if conn.hello => {
+                    let mut conn = self.cakajoci.swap_remove(i);
+                    let razlog = self.preveri_geslo(&conn, geslo.as_deref());
+                    let st_gledalcev = self.clients.iter().filter(|c| c.gledalec()).count();
+                    match razlog {
+                        Some(reason) => Server::reject(&mut conn, reason),
+                        None if gledalec && st_gledalcev >= MAX_GLEDALCEV => Server::reject(&mut conn, "too many spectators".to_string()),
+                        None if gledalec => self.sprejmi(conn, token, true),
+                        // mesto, ki se je sprostilo, pripada tistim, ki ze cakajo
+                        None if (self.poln() || !self.cakalna_vrsta.is_empty()) && !self.ima_mesto(token) => self.postavi_v_vrsto(conn, token),
+                        None => self.sprejmi(conn, token, false),
+                    }
+                    continue;
+                },
+                Ok(Some(_)) => {
+                    let mut conn = self.cakajoci.swap_remove(i);
+                    let razlog = if conn.hello { "expected login" } else { "expected hello" };
+                    Server::reject(&mut conn, razlog.to_string());
+                    continue;
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    eprintln!("err handshake from {}: {}", conn.addr, e);
+                    self.cakajoci.swap_remove(i);
+                    continue;
+                }
+            }
+
+            if prebrano.is_err() || conn.zacetek.elapsed() > HANDSHAKE_TIMEOUT {
+                println!("client {} dropped during handshake", conn.addr);
+                self.cakajoci.swap_remove(i);
+                continue;
+            }
+
+            i += 1;
+        }
+    }
+
+    pub fn listen(&mut self) {
+        let nove: Vec<Box<dyn Povezava>> = self.poslusalci.iter_mut()
+            .flat_map(|p| p.sprejmi())
+            .collect();
+        for povezava in nove {
+            let addr = povezava.addr();
+            println!("new client connected from {}", addr);
+            let mut conn = PendingConnection {
+                povezava,
+                addr,
+                zacetek: Instant::now(),
+                hello: false,
+            };
+            if self.je_ban(&conn) {
+                Server::reject(&mut conn, "you are banned from this server".to_string());
+                continue;
+            }
+            self.cakajoci.push(conn);
+        }
+
+        self.napacna_gesla.retain(|_, (_, zadnji)| zadnji.elapsed() < ZAKLEP_PO_NAPACNIH_GESLIH);
+        self.obdelaj_cakajoce();
+        self.pocisti_prekinjene();
+        self.posodobi_cakalno_vrsto();
+
+        let igralci = self.st_igralcev() as u32;
+        if let Some(oglasevalec) = &mut self.oglasevalec {
+            oglasevalec.odgovori(&OglasStreznika {
+                ime: self.ime.clone(),
+                mapa: self.mapa.clone(),
+                igralci,
+                max_igralcev: self.max_igralcev as u32,
+                port: self.port,
+                protocol_version: PROTOCOL_VERSION,
+                geslo: self.geslo.is_some(),
+            });
         }
-        if let Some(client) = self.clients.iter().find(|c| c.state.id == id) {
+    }
+
+    fn najdi_ime_za_id(&self, id: u32) -> &str {
+        if let Some(client) = self.clients.iter().find(|c| c.id == id) {
             return &client.user_name;
         }
         return "player";
@@ -149,29 +682,16 @@ impl Server {
 
             let mut umrl = false;
 
-            if *id == 0 {
-                if self.health > 0 {
-                    self.health -= 10;
-                    if self.health <= 0 {
-                        self.health = 0;
-                        self.respawn_timer = RESPAWN_TIME;
-                        umrl = true;
-                    }
-                    particles::spawn(particles_pos, None, &HIT_PARTICLES);
-                    self.send_msg_all(Message::HitParticles(particles_pos.into()));
-                }
-            }
-            else if let Some(client) = self.clients.iter_mut().find(|c| c.state.id == *id && c.health > 0) {
+            if let Some(client) = self.clients.iter_mut().find(|c| c.id == *id && c.health > 0) {
                 client.health -= 10;
                 if client.health <= 0 {
                     client.health = 0;
-                    client.respawn_timer = RESPAWN_TIME;
+                    client.respawn_timer = self.respawn_time;
                     umrl = true;
                 }
                 let msg = Message::Attack(client.health);
                 Server::send_msg(client, msg);
 
-                particles::spawn(particles_pos, None, &HIT_PARTICLES);
                 self.send_msg_all(Message::HitParticles(particles_pos.into()));
             }
 
@@ -181,137 +701,553 @@ impl Server {
 
                 let ime_napadalca = self.najdi_ime_za_id(napadalec_id);
                 let ime_umrlega = self.najdi_ime_za_id(*id);
-                pop_up_msg(format!("{} killed {}", ime_napadalca, ime_umrlega));
+                println!("{} killed {}", ime_napadalca, ime_umrlega);
 
-                if napadalec_id == 0 {
-                    self.kills += 1;
-                }
-                else if let Some(client) = self.clients.iter_mut().find(|c| c.state.id == napadalec_id) {
+                if let Some(client) = self.clients.iter_mut().find(|c| c.id == napadalec_id) {
                     client.kills += 1;
                 }
-                self.nov_leaderboard = true;
             }
         }
     }
 
-    pub fn attack_host(&mut self, player: &Player) {
-        let hitbox = Player::calc_sword_hitbox(player.position, player.attack_time, player.razdalja_meca, player.rotation);
-        let found = physics::area_query(hitbox, LAYER_PLAYER);
+    /// pozicije igralcev, kot jih je videl odjemalec ob ticku pogleda
+    fn igralci_ob(&self, pogled: f32) -> Vec<(u32, AABB)> {
+        let (Some(najstarejsi), Some(najnovejsi)) = (self.zgodovina.front(), self.zgodovina.back()) else {
+            return Vec::new();
+        };
+        let pogled = pogled.clamp(najstarejsi.tick as f32, najnovejsi.tick as f32);
 
-        let pozicija = player.position + vec2(8.0, 12.0);
-        let smer = Vec2::from_angle(player.rotation);
-        self.handle_attack(found, 0, pozicija, smer);
+        let i = self.zgodovina.iter().position(|z| z.tick as f32 >= pogled).unwrap_or(0);
+        let b = &self.zgodovina[i];
+        let a = if i > 0 { &self.zgodovina[i - 1] } else { b };
+        if a.tick == b.tick {
+            return b.igralci.clone();
+        }
+        let t = (pogled - a.tick as f32) / (b.tick - a.tick) as f32;
+
+        b.igralci.iter()
+            .map(|(id, aabb_b)| {
+                let Some((_, aabb_a)) = a.igralci.iter().find(|(id_a, _)| id_a == id) else {
+                    return (*id, *aabb_b);
+                };
+                let pozicija = vec2(aabb_a.x, aabb_a.y).lerp(vec2(aabb_b.x, aabb_b.y), t);
+                (*id, AABB::from_vec(pozicija, vec2(aabb_b.w, aabb_b.h)))
+            })
+            .collect()
     }
 
-    fn attack(&mut self, conn_i: usize) {
+    /// pogled je tick stanja, ki ga je napadalec videl ob napadu
+    fn attack(&mut self, conn_i: usize, pogled: f32) {
         let client = &self.clients[conn_i];
-        let state = &client.state;
+        let Some(g) = &client.gibanje else { return };
+        let position = g.trenutna_pozicija();
+
+        let hitbox = Player::calc_sword_hitbox(position, g.attack_time, g.razdalja_meca, g.rotation);
+        let found = self.igralci_ob(pogled).into_iter()
+            .filter(|(_, aabb)| aabb.overlaps(hitbox))
+            .collect();
+
+        let pozicija = position + vec2(8.0, 12.0);
+        let smer = Vec2::from_angle(g.rotation);
+        self.handle_attack(found, client.id, pozicija, smer);
+    }
 
-        let hitbox = Player::calc_sword_hitbox(state.position.into(), state.attack_time, state.razdalja_meca, state.rotation);
-        let found = physics::area_query(hitbox, LAYER_PLAYER);
+    fn simuliraj_vhode(&mut self, vhodi: Vec<PlayerInput>, conn_i: usize) {
+        for vhod in vhodi.into_iter().take(MAX_VHODOV_NA_MSG) {
+            let client = &mut self.clients[conn_i];
+            // gledalec poslje prazne vhode le za potrditev posnetkov
+            let Some(gibanje) = &mut client.gibanje else { return };
+            if !vhod.veljaven() {
+                continue;
+            }
+            if vhod.seq <= client.zadnji_vhod {
+                continue; // ze simuliran
+            }
+            client.zadnji_vhod = vhod.seq;
+
+            // korak brez casa bi igralcu ponastavil skoke, zato ga streznik podaljsa
+            let delta = vhod.delta.clamp(MIN_DELTA, MAX_DELTA);
+            if client.health <= 0 || delta > client.zaloga_vhodov {
+                continue;
+            }
+            client.zaloga_vhodov -= delta;
 
-        let pozicija = Vec2::from(client.state.position) + vec2(8.0, 12.0);
-        let smer = Vec2::from_angle(client.state.rotation);
-        self.handle_attack(found, state.id, pozicija, smer);
+            gibanje.simuliraj(&vhod);
+            if gibanje.attack_time == 0.0 {
+                self.attack(conn_i, vhod.pogled);
+            }
+        }
     }
 
     fn handle_msg(&mut self, msg: Message, conn_i: usize) {
         let client = &mut self.clients[conn_i];
         match msg {
-            Message::PlayerState(state) => {
-                let id = client.state.id;
-                client.state = state.clone();
-                client.state.id = id;
-
-                physics::premakni_obj_na(&client.aabb_ref, client.state.position.into());
-
-                if state.attack_time == 0.0 {
-                    self.attack(conn_i);
-                }
+            Message::PlayerInput { potrjen_tick, vhodi } => {
+                client.potrjen_tick = client.potrjen_tick.max(potrjen_tick);
+                self.simuliraj_vhode(vhodi, conn_i);
             },
+            Message::UserInfo(_) if client.gledalec() => {},
             Message::UserInfo((_id, name, _kills)) => {
-                pop_up_msg(format!("{} joined", name));
+                // odjemalec ime preveri ze sam, tega ne bi smeli dobiti
+                let name = match ocisti_ime(&name) {
+                    Ok(name) => name,
+                    Err(e) if !client.predstavljen => {
+                        eprintln!("err invalid name from {}: {}", client.addr, e);
+                        self.za_odstranitev.push((client.id, format!("invalid name: {}", e)));
+                        return;
+                    },
+                    Err(e) => {
+                        Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, format!("invalid name: {}", e))));
+                        return;
+                    }
+                };
+                let id = client.id;
+                let name = self.unikatno_ime(&name, id);
+                let client = &mut self.clients[conn_i];
+
+                // prvi UserInfo na povezavi, tudi ob vrnitvi po prekinitvi
+                if !client.predstavljen {
+                    println!("{} joined", name);
+                    client.predstavljen = true;
+                    client.user_name = name.clone();
+                    let msg = Message::UserInfo((id, name, client.kills));
+                    self.send_msg_all(msg);
+                    return;
+                }
+                if client.user_name == name {
+                    return;
+                }
+
+                if client.zaloga_sporocil < 1.0 {
+                    let opozorilo = "you are changing your name too fast".to_string();
+                    Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, opozorilo)));
+                    return;
+                }
+                client.zaloga_sporocil -= 1.0;
+                println!("{} is now {}", client.user_name, name);
                 client.user_name = name.clone();
-                let msg = Message::UserInfo((client.state.id, name, client.kills));
+                self.send_msg_all(Message::Preimenovanje((id, name)));
+            }
+            Message::ChatMessage((_id, tekst)) => {
+                let tekst = ocisti_sporocilo(&tekst);
+                if tekst.is_empty() {
+                    return;
+                }
+                if client.gledalec() {
+                    let odgovor = "spectators cannot chat".to_string();
+                    Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, odgovor)));
+                    return;
+                }
+                if client.zaloga_sporocil < 1.0 {
+                    let opozorilo = "you are sending messages too fast".to_string();
+                    Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, opozorilo)));
+                    return;
+                }
+                client.zaloga_sporocil -= 1.0;
+                if let Some(ukaz) = tekst.strip_prefix('/') {
+                    if client.povezava.lokalna() {
+                        self.cakajoci_ukazi.push((client.id, ukaz.to_string()));
+                    } else {
+                        let odgovor = "only the host can use commands".to_string();
+                        Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, odgovor)));
+                    }
+                    return;
+                }
+                println!("{}: {}", client.user_name, tekst);
+                let msg = Message::ChatMessage((client.id, tekst));
                 self.send_msg_all(msg);
             }
+            Message::Pong(seq) => {
+                if let Some((cakajoc, poslan)) = client.cakajoc_ping {
+                    if cakajoc == seq {
+                        client.ping = poslan.elapsed();
+                        client.cakajoc_ping = None;
+                    }
+                }
+            }
             _ => {},
         }
     }
 
+    /// vsem poslje ping in zadnje izmerjene pinge vseh igralcev
+    fn poslji_pinge(&mut self) {
+        if Instant::now() < self.naslednji_ping {
+            return;
+        }
+        self.naslednji_ping = Instant::now() + PING_INTERVAL;
+        self.ping_seq += 1;
+
+        let mut pingi = Vec::new();
+        for client in &mut self.clients {
+            client.cakajoc_ping = Some((self.ping_seq, Instant::now()));
+            Server::send_msg(client, Message::Ping(self.ping_seq));
+            if !client.gledalec() {
+                pingi.push((client.id, client.ping.as_millis().min(u16::MAX as u128) as u16));
+            }
+        }
+        self.send_msg_all(Message::Pingi(pingi));
+
+        for cakajoc in &mut self.cakalna_vrsta {
+            if let Err(e) = cakajoc.conn.povezava.send(&Message::Ping(self.ping_seq)) {
+                eprintln!("err socket write: {:?}", e);
+            }
+        }
+    }
+
+    /// mesto igralca se hrani CAS_ZA_VRNITEV, da se lahko vrne
+    fn prekini(&mut self, conn_i: usize) {
+        let conn = self.clients.swap_remove(conn_i);
+        println!("client disconnected {:?}", conn.addr);
+
+        // gledalec nima mesta, ki bi ga bilo treba hraniti
+        let Some(gibanje) = &conn.gibanje else {
+            self.sprosti_id(conn.id);
+            return;
+        };
+        self.prekinjene.push(PrekinjenaSeja {
+            token: conn.token,
+            id: conn.id,
+            pozicija: gibanje.trenutna_pozicija(),
+            user_name: conn.user_name,
+            kills: conn.kills,
+            health: conn.health,
+            respawn_timer: conn.respawn_timer,
+            od: Instant::now(),
+        });
+    }
+
+    fn pocisti_prekinjene(&mut self) {
+        let mut i = 0;
+        while i < self.prekinjene.len() {
+            if self.prekinjene[i].od.elapsed() > CAS_ZA_VRNITEV {
+                let seja = self.prekinjene.swap_remove(i);
+                self.sprosti_id(seja.id);
+                self.send_msg_all(Message::PlayerDisconnected(seja.id));
+                println!("{} left", seja.user_name);
+                continue;
+            }
+            i += 1;
+        }
+    }
+
     pub fn recv(&mut self) {
         let mut i: i32 = 0;
         while i < self.clients.len() as i32 {
+            let client = &mut self.clients[i as usize];
+            if let Err(e) = client.povezava.poslji_cakajoce() {
+                client.napaka.get_or_insert(NetError::Send(e));
+            }
+            let mut napaka = client.povezava.preberi().err().map(NetError::Recv);
+            let mut prejeto = false;
 
+            // najprej obdelamo vsa sporocila, ki so prispela pred napako
             loop {
-                match bincode::deserialize_from::<&mut BufReader<TcpStream>, Message>(&mut self.clients[i as usize].reader) {
-                    Ok(msg) => {
+                match self.clients[i as usize].povezava.naslednji_msg() {
+                    Ok(Some(msg)) => {
+                        prejeto = true;
                         self.handle_msg(msg, i as usize);
                         //println!("recv: {:?}", msg);
                     },
+                    Ok(None) => break,
                     Err(e) => {
-                        if handle_bincode_error(e) {
-                            println!("client disconnected {:?}", self.clients[i as usize].addr);
-
-                            self.send_msg_all(Message::PlayerDisconnected(self.clients[i as usize].state.id));
-                            pop_up_msg(format!("{} left", self.clients[i as usize].user_name));
-                            self.nov_leaderboard = true;
-
-                            self.clients.swap_remove(i as usize);
-                            i -= 1;
-                        }
+                        napaka = Some(NetError::Recv(e));
                         break;
                     }
                 }
             }
 
+            let client = &mut self.clients[i as usize];
+            if prejeto {
+                client.zadnji_prejem = Instant::now();
+            } else if client.zadnji_prejem.elapsed() > self.timeout {
+                napaka = napaka.or(Some(NetError::Timeout(self.timeout)));
+            }
+
+            if let Some(e) = client.napaka.take().or(napaka) {
+                match e {
+                    NetError::Recv(RecvError::Disconnect) => {},
+                    _ => eprintln!("err client {:?}: {}", self.clients[i as usize].addr, e),
+                }
+                self.prekini(i as usize);
+                i -= 1;
+            }
+
             i += 1;
         }
-    }
 
-    pub fn send_to_all(&mut self, bytes: &[u8]) {
-        for conn in self.clients.iter_mut() {
-            if let Err(e) = conn.reader.get_mut().write(bytes) {
-                eprintln!("err socket write: {:?}", e);
+        // ukazi lahko odstranijo igralce, zato sele po zanki
+        for (id, razlog) in std::mem::take(&mut self.za_odstranitev) {
+            if let Some(i) = self.clients.iter().position(|c| c.id == id) {
+                self.odstrani(i, razlog);
             }
         }
+        for (id, ukaz) in std::mem::take(&mut self.cakajoci_ukazi) {
+            let odgovor = self.izvedi_ukaz(&ukaz);
+            if let Some(client) = self.clients.iter_mut().find(|c| c.id == id) {
+                for vrstica in odgovor.lines() {
+                    Server::send_msg(client, Message::ChatMessage((ID_STREZNIKA, vrstica.to_string())));
+                }
+            }
+        }
+    }
+
+    /// izvede ukaz iz konzole ali klepeta gostitelja in vrne odgovor
+    pub fn izvedi_ukaz(&mut self, vrstica: &str) -> String {
+        let ukaz = match admin::razcleni_ukaz(vrstica) {
+            Ok(ukaz) => ukaz,
+            Err(e) => return e,
+        };
+
+        match ukaz {
+            AdminUkaz::Pomoc => format!("{}\n{}", admin::POMOC_UKAZI, admin::NASTAVITVE),
+            AdminUkaz::Igralci => {
+                let vrstice: Vec<String> = self.clients.iter()
+                    .map(|c| format!("{} {} {} {} ms", c.id, c.user_name, c.addr.ip(), c.ping.as_millis()))
+                    .collect();
+                if vrstice.is_empty() { "no players".to_string() } else { vrstice.join("\n") }
+            },
+            AdminUkaz::Kick(kdo) => {
+                let Some(i) = self.najdi_igralca(&kdo) else {
+                    return format!("no player {}", kdo);
+                };
+                if self.clients[i].povezava.lokalna() {
+                    return "cannot kick the host".to_string();
+                }
+                let ime = self.clients[i].user_name.clone();
+                self.odstrani(i, "kicked by the host".to_string());
+                format!("kicked {}", ime)
+            },
+            AdminUkaz::Ban(kdo) => {
+                let ip = match kdo.parse::<IpAddr>() {
+                    Ok(ip) => ip,
+                    Err(_) => match self.najdi_igralca(&kdo) {
+                        Some(i) if self.clients[i].povezava.lokalna() => return "cannot ban the host".to_string(),
+                        Some(i) => self.clients[i].addr.ip(),
+                        None => return format!("no player {}", kdo),
+                    },
+                };
+                if !self.bani.contains(&ip) {
+                    self.bani.push(ip);
+                    self.shrani_bane();
+                }
+                while let Some(i) = self.clients.iter().position(|c| c.addr.ip() == ip && !c.povezava.lokalna()) {
+                    self.odstrani(i, "banned by the host".to_string());
+                }
+                self.zavrni_bane();
+                format!("banned {}", ip)
+            },
+            AdminUkaz::Unban(ip) => {
+                let Ok(ip) = ip.parse::<IpAddr>() else {
+                    return format!("invalid ip address: {}", ip);
+                };
+                let st = self.bani.len();
+                self.bani.retain(|b| *b != ip);
+                if self.bani.len() == st {
+                    return format!("{} is not banned", ip);
+                }
+                self.shrani_bane();
+                format!("unbanned {}", ip)
+            },
+            AdminUkaz::Mapa(ime) => {
+                let Some(pot) = pot_mape(&ime).filter(|p| Path::new(p).exists()) else {
+                    return format!("no map {}", ime);
+                };
+                self.mapa = ime.clone();
+                self.nova_mapa = Some(pot);
+                self.send_msg_all(Message::Mapa(ime.clone()));
+                self.restart();
+                format!("map changed to {}", ime)
+            },
+            AdminUkaz::Restart => {
+                self.restart();
+                "match restarted".to_string()
+            },
+            AdminUkaz::Set(kaj, vrednost) => {
+                let Some(v) = vrednost.parse::<f32>().ok().filter(|v| v.is_finite() && *v > 0.0) else {
+                    return format!("invalid value: {}", vrednost);
+                };
+                // prevelika vrednost ne gre v Duration
+                let trajanje = |s: f32| Duration::try_from_secs_f32(s).map_err(|_| format!("invalid value: {}", vrednost));
+                match kaj.as_str() {
+                    "respawn_time" => self.respawn_time = v,
+                    "send_rate" => self.hitrost_posiljanja = v,
+                    "max_rewind" => match trajanje(v / 1000.0) {
+                        Ok(d) => self.max_rewind = d,
+                        Err(e) => return e,
+                    },
+                    "timeout" => match trajanje(v) {
+                        Ok(d) => self.timeout = d,
+                        Err(e) => return e,
+                    },
+                    _ => return format!("unknown setting: {}\n{}", kaj, admin::NASTAVITVE),
+                }
+                format!("{} = {}", kaj, v)
+            },
+        }
+    }
+
+    /// ce ime ze ima drug igralec (tudi prekinjen), doda _2, _3, ...
+    fn unikatno_ime(&self, ime: &str, id: u32) -> String {
+        let zaseden = |kandidat: &str| {
+            let kandidat = kandidat.to_lowercase();
+            self.clients.iter()
+                .filter(|c| c.id != id && !c.gledalec())
+                .map(|c| c.user_name.as_str())
+                .chain(self.prekinjene.iter().filter(|s| s.id != id).map(|s| s.user_name.as_str()))
+                .any(|ime| ime.to_lowercase() == kandidat)
+        };
+        if !zaseden(ime) {
+            return ime.to_string();
+        }
+
+        (2..)
+            .map(|n| {
+                let pripona = format!("_{}", n);
+                let osnova: String = ime.chars().take(MAX_DOLZINA_IMENA - pripona.len()).collect();
+                osnova + &pripona
+            })
+            .find(|kandidat| !zaseden(kandidat))
+            .unwrap()
+    }
+
+    /// po imenu ali id
+    fn najdi_igralca(&self, kdo: &str) -> Option<usize> {
+        self.clients.iter().position(|c| c.user_name == kdo)
+            .or_else(|| {
+                let id = kdo.parse::<u32>().ok()?;
+                self.clients.iter().position(|c| c.id == id)
+            })
+    }
+
+    /// za razliko od prekini se igralec ne more vrniti s tokenom
+    fn odstrani(&mut self, conn_i: usize, razlog: String) {
+        let mut conn = self.clients.swap_remove(conn_i);
+        println!("{} removed: {}", conn.user_name, razlog);
+        let _ = conn.povezava.send(&Message::Reject { reason: razlog });
+        conn.povezava.disconnect();
+        self.sprosti_id(conn.id);
+        if !conn.gledalec() {
+            self.send_msg_all(Message::PlayerDisconnected(conn.id));
+        }
+    }
+
+    /// vsi ozivijo na novih mestih in zacnejo brez ubojev
+    fn restart(&mut self) {
+        for client in &mut self.clients {
+            let Some(gibanje) = &mut client.gibanje else { continue };
+            client.kills = 0;
+            client.health = 100;
+            client.respawn_timer = 0.0;
+            let pozicija = Server::get_respawn_location();
+            gibanje.nastavi_pozicijo(pozicija);
+            Server::send_msg(client, Message::Respawn(pozicija.into()));
+        }
+        for seja in &mut self.prekinjene {
+            seja.kills = 0;
+            seja.health = 100;
+            seja.pozicija = Server::get_respawn_location();
+        }
+        self.send_msg_all(Message::Restart);
+        println!("match restarted");
+    }
+
+    /// prebere bane iz datoteke, ce obstaja, in vanjo shranjuje nove
+    pub fn nalozi_bane(&mut self, pot: PathBuf) {
+        match std::fs::read_to_string(&pot) {
+            Ok(vsebina) => {
+                for vrstica in vsebina.lines().map(str::trim).filter(|v| !v.is_empty()) {
+                    match vrstica.parse() {
+                        Ok(ip) => self.bani.push(ip),
+                        Err(_) => eprintln!("err invalid ban in {}: {}", pot.display(), vrstica),
+                    }
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => eprintln!("err reading bans from {}: {}", pot.display(), e),
+        }
+        self.datoteka_banov = Some(pot);
+    }
+
+    fn shrani_bane(&self) {
+        let Some(pot) = &self.datoteka_banov else { return };
+        let vsebina: String = self.bani.iter().map(|ip| format!("{}\n", ip)).collect();
+        if let Err(e) = std::fs::write(pot, vsebina) {
+            eprintln!("err saving bans to {}: {}", pot.display(), e);
+        }
     }
 
     pub fn send_msg_all(&mut self, msg: Message) {
-        let send_buf = bincode::serialize(&msg).unwrap();
-        self.send_to_all(&send_buf);
+        for conn in self.clients.iter_mut() {
+            Server::send_msg(conn, msg.clone());
+        }
     }
 
+    /// napaka se shrani, povezavo prekine naslednji recv
     pub fn send_msg(conn: &mut ServerConnection, msg: Message) {
-        let send_buf = bincode::serialize(&msg).unwrap();
-        conn.reader.get_mut().write(&send_buf).unwrap();
+        if conn.napaka.is_some() {
+            return;
+        }
+        if let Err(e) = conn.povezava.send(&msg) {
+            conn.napaka = Some(NetError::Send(e));
+        }
     }
 
-    pub fn narisi_cliente(&self, tekstura: &Texture2D) {
-        for conn in &self.clients {
-            if conn.health <= 0 { continue; }
-            //draw_rectangle_lines(conn.state.position.0, conn.state.position.1, 16.0, 28.0, 1.0, macroquad::color::RED);
-            let state = &conn.state;
-            Player::narisi_iz(tekstura, state.position.into(), state.anim_frame.into(), state.rotation, state.razdalja_meca, state.attack_time, &conn.user_name, -1);
+    /// poslje stanje, ce je od zadnjega posiljanja minil en tick mreze
+    pub fn poslji_vse_state(&mut self) {
+        let interval = 1.0 / self.hitrost_posiljanja;
+        if self.cas_od_posiljanja < interval {
+            return;
+        }
+        self.cas_od_posiljanja = (self.cas_od_posiljanja - interval).min(interval);
+
+        let stanja: Vec<KvantiziranoStanje> = self.clients.iter()
+            .filter(|c| c.health > 0)
+            .filter_map(|c| {
+                let g = c.gibanje.as_ref()?;
+                Some(KvantiziranoStanje::iz(&State {
+                    id: c.id,
+                    position: g.trenutna_pozicija().into(),
+                    rotation: g.rotation,
+                    anim_frame: g.get_anim().izr_frame_xy().into(),
+                    attack_time: g.attack_time,
+                    razdalja_meca: g.razdalja_meca,
+                    zadnji_vhod: c.zadnji_vhod,
+                    gibanje: g.stanje(),
+                }))
+            })
+            .collect();
+
+        self.tick += 1;
+        self.shrani_zgodovino();
+
+        let tick = self.tick;
+        for client in &mut self.clients {
+            let osnova = client.poslani_posnetki.iter().find(|(t, _)| *t == client.potrjen_tick);
+            let (osnova_tick, osnova) = match osnova {
+                Some((t, stanja)) => (*t, stanja.as_slice()),
+                None => (0, [].as_slice()),
+            };
+            let podatki = snapshot::zakodiraj(&stanja, osnova, client.id);
+
+            client.poslani_posnetki.push_back((tick, stanja.clone()));
+            if client.poslani_posnetki.len() > ZGODOVINA_POSNETKOV {
+                client.poslani_posnetki.pop_front();
+            }
+            Server::send_msg(client, Message::Posnetek { tick, osnova: osnova_tick, podatki });
         }
     }
 
-    pub fn poslji_vse_state(&mut self, player: &Player) {
-        let mut states: Vec<State> = self.clients.iter()
+    fn shrani_zgodovino(&mut self) {
+        let zdaj = Instant::now();
+        let igralci = self.clients.iter()
             .filter(|c| c.health > 0)
-            .map(|c| c.state.clone())
+            .filter_map(|c| Some((c.id, AABB::from_vec(c.gibanje.as_ref()?.trenutna_pozicija(), vec2(16.0, 28.0)))))
             .collect();
-        if self.health > 0 {
-            states.push(State {
-                id: 0, // gazda/host id
-                position: (player.position.x, player.position.y),
-                rotation: player.rotation,
-                anim_frame: player.get_anim().izr_frame_xy().into(),
-                attack_time: player.attack_time,
-                razdalja_meca: player.razdalja_meca,
-            });
+        self.zgodovina.push_back(ZgodovinaTick { tick: self.tick, cas: zdaj, igralci });
+
+        while self.zgodovina.front().is_some_and(|z| zdaj.duration_since(z.cas) > self.max_rewind) {
+            self.zgodovina.pop_front();
         }
-        self.send_msg_all(Message::AllPlayersState(states));
     }
 
     fn get_respawn_location() -> Vec2 {
@@ -321,123 +1257,377 @@ impl Server {
         )
     }
 
-    pub fn posodobi(&mut self, delta: f32, player: &mut Player) {
-        if self.health <= 0 {
-            self.respawn_timer -= delta;
-            if self.respawn_timer <= 0.0 {
-                self.health = 100;
-                player.nastavi_pozicijo(Server::get_respawn_location());
-            }
-        }
+    /// en tick streznika, enak za gostitelja in dedicated streznik
+    pub fn tick(&mut self, delta: f32) {
+        self.listen();
+        self.recv();
+        self.posodobi(delta);
+
+        // posnetki naj vsebujejo ze razresene trke med igralci
+        physics::resi_trke();
+        physics::resi_trke();
+
+        self.poslji_vse_state();
+    }
+
+    pub fn posodobi(&mut self, delta: f32) {
+        self.cas_od_posiljanja += delta;
+        self.poslji_pinge();
 
         for client in &mut self.clients {
+            client.zaloga_vhodov = (client.zaloga_vhodov + delta).min(MAX_ZALOGA_VHODOV);
+            client.zaloga_sporocil = (client.zaloga_sporocil + delta * SPOROCILA_NA_SEKUNDO).min(MAX_ZALOGA_SPOROCIL);
+
             if client.health <= 0 {
                 client.respawn_timer -= delta;
-                if client.respawn_timer <= 0.0 {
+                if let (true, Some(gibanje)) = (client.respawn_timer <= 0.0, &mut client.gibanje) {
                     client.health = 100;
-                    let msg = Message::Respawn(Server::get_respawn_location().into());
-                    client.state.position.1 = -1000.0; // da ne dobim 2x smrti
-                    Server::send_msg(client, msg);
+                    let pozicija = Server::get_respawn_location();
+                    gibanje.nastavi_pozicijo(pozicija);
+                    Server::send_msg(client, Message::Respawn(pozicija.into()));
                 }
             }
         }
 
-        if self.health > 0 && player.position.y > FALLOFF_Y {
-            self.health = 0;
-            self.respawn_timer = RESPAWN_TIME;
-
-            self.send_msg_all(Message::PlayerDied((0, u32::MAX)));
-            pop_up_msg(format!("{} killed himself", self.user_name));
-        }
-
         let mut died_msgs_buf = Vec::new();
 
         for client in &mut self.clients {
-            if client.health > 0 && client.state.position.1 > FALLOFF_Y {
+            let padel = client.gibanje.as_ref().is_some_and(|g| g.trenutna_pozicija().y > FALLOFF_Y);
+            if client.health > 0 && padel {
                 client.health = 0;
-                client.respawn_timer = RESPAWN_TIME;
+                client.respawn_timer = self.respawn_time;
                 Server::send_msg(client, Message::Attack(client.health));
 
-                died_msgs_buf.append(&mut bincode::serialize(&Message::PlayerDied((client.state.id, u32::MAX))).unwrap());
-                pop_up_msg(format!("{} killed himself", client.user_name));
+                died_msgs_buf.push(Message::PlayerDied((client.id, u32::MAX)));
+                println!("{} killed himself", client.user_name);
             }
         }
 
-        if died_msgs_buf.len() > 0 {
-            self.send_to_all(&died_msgs_buf);
-        }
-    }
-
-    pub fn get_leaderboard_data(&self) -> Vec<(String, i32)> {
-        let mut vec = Vec::new();
-        vec.push((self.user_name.clone(), self.kills));
-
-        for client in &self.clients {
-            vec.push((client.user_name.clone(), client.kills));
+        for msg in died_msgs_buf {
+            self.send_msg_all(msg);
         }
-
-        vec.sort_by(|a, b| b.1.cmp(&a.1));
-        vec
     }
 }
 
 struct UserInfo {
     name: String,
     kills: i32,
+    /// v milisekundah
+    ping: u16,
+}
+
+/// stanje drugega igralca s casom prejema
+struct Posnetek {
+    cas: Instant,
+    tick: u32,
+    state: State,
+}
+
+#[derive(PartialEq)]
+pub enum ClientStatus {
+    /// cakamo na Welcome
+    Povezovanje,
+    Povezan,
+    Zavrnjen(String),
+    /// streznik je poln, pozicija v cakalni vrsti
+    VCakalniVrsti(u32),
+    /// povezava je prekinjena, cakamo na naslednji poskus
+    PonovnoPovezovanje,
+    /// povezave ni mogoce obnoviti
+    Prekinjen(String),
 }
 
 pub struct Client {
     pub id: u32,
-    reader: BufReader<TcpStream>,
+    pub status: ClientStatus,
+    user_name: String,
+    /// poslje se v Hello, tudi pri ponovnem povezovanju
+    geslo: Option<String>,
+    pub gledalec: bool,
+    povezava: Box<dyn Povezava>,
+    /// naslov, port in tcp, ce se lahko ponovno povezemo (gostitelj se ne more)
+    naslov: Option<(String, u16, bool)>,
+    /// od streznika, da se po prekinitvi vrnemo na svoje mesto
+    token: Option<u64>,
+    /// zaporedni neuspesni poskusi ponovne povezave
+    poskusi: u32,
+    naslednji_poskus: Instant,
+    zacetek_povezovanja: Instant,
+    /// napaka pri posiljanju, obravnava jo naslednji recv
+    napaka: Option<NetError>,
+    zadnji_prejem: Instant,
+    pub timeout: Duration,
     net_states: Vec<State>,
+    /// prejeti posnetki, ki so lahko osnova za razliko
+    prejeti_posnetki: VecDeque<(u32, Vec<KvantiziranoStanje>)>,
+    posnetki: HashMap<u32, VecDeque<Posnetek>>,
+    zadnji_tick: u32,
+    /// zamik upodabljanja drugih igralcev
+    pub interpolacija: Duration,
     net_users: HashMap<u32, UserInfo>,
     pub health: i32,
+    /// kam naj se igralec premakne, ko ga streznik ozivi
+    pub respawn: Option<Vec2>,
+    /// ime mape, ki jo je treba nalozit
+    pub nova_mapa: Option<String>,
     pub nov_leaderboard: bool,
+    /// vhodi, ki jih streznik se ni potrdil
+    nepotrjeni_vhodi: VecDeque<PlayerInput>,
+    /// kolikokrat na sekundo se poslje vhode
+    pub hitrost_posiljanja: f32,
+    cas_od_posiljanja: f32,
+    /// zadnje stanje lokalnega igralca od streznika, ki se ni bilo uporabljeno
+    pub potrjeno_stanje: Option<State>,
 }
 
 impl Client {
-    pub fn new(addr: &str, name: String) -> Client {
-        let mut stream = match TcpStream::connect((addr, PORT)) {
-            Ok(s) => s,
-            Err(e) => panic!("ERROR povezava neuspešna: {}", e),
-        };
-        prepare_socket(&mut stream);
-        let msg = Message::UserInfo((u32::MAX, name, 0));
-        stream.write(&bincode::serialize(&msg).unwrap()).unwrap();
-        Client {
+    pub fn new(addr: &str, port: u16, name: String, geslo: Option<String>, gledalec: bool, tcp: bool) -> Result<Client, NetError> {
+        let povezava = if tcp { connect_tcp(addr, port) } else { connect_udp(addr, port) };
+        let mut client = Client::with_connection(povezava.map_err(NetError::Connect)?, name, geslo, gledalec);
+        client.naslov = Some((addr.to_string(), port, tcp));
+        Ok(client)
+    }
+
+    pub fn with_connection(povezava: Box<dyn Povezava>, name: String, geslo: Option<String>, gledalec: bool) -> Client {
+        let mut client = Client {
             id: u32::MAX,
-            reader: BufReader::new(stream),
+            status: ClientStatus::Povezovanje,
+            user_name: name,
+            geslo,
+            gledalec,
+            povezava,
+            naslov: None,
+            token: None,
+            poskusi: 0,
+            naslednji_poskus: Instant::now(),
+            zacetek_povezovanja: Instant::now(),
+            napaka: None,
+            zadnji_prejem: Instant::now(),
+            timeout: PRIVZETI_TIMEOUT,
             net_states: Vec::new(),
+            prejeti_posnetki: VecDeque::new(),
+            posnetki: HashMap::new(),
+            zadnji_tick: 0,
+            interpolacija: PRIVZETA_INTERPOLACIJA,
             net_users: HashMap::new(),
             health: 100,
+            respawn: None,
+            nova_mapa: None,
             nov_leaderboard: true,
+            nepotrjeni_vhodi: VecDeque::new(),
+            hitrost_posiljanja: 1.0 / TICK_TIME,
+            cas_od_posiljanja: 0.0,
+            potrjeno_stanje: None,
+        };
+        client.poslji_hello();
+        client
+    }
+
+    fn poslji_hello(&mut self) {
+        self.status = ClientStatus::Povezovanje;
+        self.zacetek_povezovanja = Instant::now();
+        self.zadnji_prejem = Instant::now();
+        let msg = Message::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            game_version: GAME_VERSION.to_string(),
+        };
+        self.send_msg(msg);
+        let msg = Message::Prijava {
+            token: self.token,
+            geslo: self.geslo.clone(),
+            gledalec: self.gledalec,
+        };
+        self.send_msg(msg);
+    }
+
+    /// stevilka poskusa, ce se odjemalec ponovno povezuje
+    pub fn ponovno_povezovanje(&self) -> Option<u32> {
+        match self.status {
+            ClientStatus::PonovnoPovezovanje => Some(self.poskusi),
+            ClientStatus::Povezovanje if self.token.is_some() => Some(self.poskusi),
+            _ => None,
+        }
+    }
+
+    fn cakaj_na_poskus(&mut self) {
+        let premor = ZACETNI_PREMOR.saturating_mul(1 << self.poskusi.min(16)).min(MAX_PREMOR);
+        self.poskusi += 1;
+        self.naslednji_poskus = Instant::now() + premor;
+        self.status = ClientStatus::PonovnoPovezovanje;
+    }
+
+    fn poskusi_ponovno(&mut self) {
+        let Some((addr, port, tcp)) = self.naslov.clone() else { return };
+        println!("reconnecting to {}:{} (attempt {})", addr, port, self.poskusi);
+
+        let povezava = if tcp { connect_tcp(&addr, port) } else { connect_udp(&addr, port) };
+        match povezava {
+            Ok(p) => {
+                self.povezava = p;
+                self.napaka = None;
+                self.poslji_hello();
+            },
+            Err(e) => {
+                eprintln!("err reconnect: {}", NetError::Connect(e));
+                self.cakaj_na_poskus();
+            }
+        }
+    }
+
+    pub fn send_msg(&mut self, msg: Message) {
+        if self.napaka.is_some() {
+            return;
+        }
+        if let Err(e) = self.povezava.send(&msg) {
+            self.napaka = Some(NetError::Send(e));
         }
     }
 
-    pub fn send(&mut self, bytes: &[u8]) {
-        self.reader.get_mut().write(bytes).unwrap();
+    /// prazna sporocila in sporocila brez povezave se zavrzejo
+    /// "/name <ime>" zamenja ime, ostalo gre v klepet
+    pub fn poslji_chat(&mut self, tekst: &str) {
+        let tekst = ocisti_sporocilo(tekst);
+        if let Some(ime) = tekst.strip_prefix("/name ") {
+            self.preimenuj(ime.trim());
+            return;
+        }
+        if !tekst.is_empty() && self.status == ClientStatus::Povezan {
+            self.send_msg(Message::ChatMessage((self.id, tekst)));
+        }
     }
 
-    pub fn handle_msg(&mut self, msg: Message, player: &mut Player) {
+    /// novo ime postane veljavno, ko ga streznik potrdi s Preimenovanje
+    pub fn preimenuj(&mut self, ime: &str) {
+        if let Err(e) = preveri_ime(ime) {
+            pop_up_msg(format!("invalid name: {}", e));
+            return;
+        }
+        if self.status == ClientStatus::Povezan && !self.gledalec {
+            self.send_msg(Message::UserInfo((self.id, ime.to_string(), 0)));
+        }
+    }
+
+    pub fn ime(&self) -> &str {
+        &self.user_name
+    }
+
+    /// gledalec nima vhodov, a mora potrjevati posnetke, da dobiva razlike
+    pub fn poslji_potrditev(&mut self, delta: f32) {
+        self.cas_od_posiljanja += delta;
+        let interval = 1.0 / self.hitrost_posiljanja;
+        if self.cas_od_posiljanja < interval {
+            return;
+        }
+        self.cas_od_posiljanja = (self.cas_od_posiljanja - interval).min(interval);
+        self.send_msg(Message::PlayerInput { potrjen_tick: self.zadnji_tick, vhodi: Vec::new() });
+    }
+
+    pub fn nepotrjeni_vhodi(&self) -> impl Iterator<Item = &PlayerInput> {
+        self.nepotrjeni_vhodi.iter()
+    }
+
+    /// doda vhod in ob vsakem intervalu posiljanja poslje vse nepotrjene,
+    /// da izguba paketa ne izgubi vhoda
+    pub fn poslji_vhod(&mut self, mut vhod: PlayerInput) {
+        vhod.pogled = self.tick_pogleda();
+        self.nepotrjeni_vhodi.push_back(vhod);
+        while self.nepotrjeni_vhodi.len() > MAX_VHODOV_NA_MSG {
+            self.nepotrjeni_vhodi.pop_front();
+        }
+
+        self.cas_od_posiljanja += vhod.delta;
+        let interval = 1.0 / self.hitrost_posiljanja;
+        if self.cas_od_posiljanja < interval {
+            return;
+        }
+        self.cas_od_posiljanja = (self.cas_od_posiljanja - interval).min(interval);
+
+        let vhodi = self.nepotrjeni_vhodi.iter().copied().collect();
+        self.send_msg(Message::PlayerInput { potrjen_tick: self.zadnji_tick, vhodi });
+    }
+
+    pub fn handle_msg(&mut self, msg: Message) {
         match msg {
-            Message::DodeljenId(id) => {
+            Message::Welcome { id, token } => {
+                if self.token.is_some() {
+                    // po ponovni povezavi streznik zacne z novo povezavo
+                    self.net_users.clear();
+                    self.posnetki.clear();
+                    self.prejeti_posnetki.clear();
+                    self.nepotrjeni_vhodi.clear();
+                    self.zadnji_tick = 0;
+                }
                 self.id = id;
+                self.token = Some(token);
+                self.poskusi = 0;
+                self.status = ClientStatus::Povezan;
                 println!("dobil id: {}", id);
+                if !self.gledalec {
+                    let msg = Message::UserInfo((id, self.user_name.clone(), 0));
+                    self.send_msg(msg);
+                }
+            },
+            Message::Reject { reason } => {
+                println!("server rejected the connection: {}", reason);
+                self.status = ClientStatus::Zavrnjen(reason);
             },
-            Message::AllPlayersState(states) => {
+            Message::Posnetek { tick, osnova, podatki } => {
+                if tick <= self.zadnji_tick {
+                    return; // star ali podvojen
+                }
+                let osnova = match self.prejeti_posnetki.iter().find(|(t, _)| *t == osnova) {
+                    Some((_, stanja)) => stanja.as_slice(),
+                    None if osnova == 0 => [].as_slice(),
+                    None => {
+                        eprintln!("missing snapshot base {}", osnova);
+                        return;
+                    }
+                };
+                let Some(stanja) = snapshot::odkodiraj(&podatki, osnova) else {
+                    eprintln!("invalid snapshot {}", tick);
+                    return;
+                };
+                let states: Vec<State> = stanja.iter().map(|s| s.v_state()).collect();
+                self.prejeti_posnetki.push_back((tick, stanja));
+                if self.prejeti_posnetki.len() > ZGODOVINA_POSNETKOV {
+                    self.prejeti_posnetki.pop_front();
+                }
+
+                if let Some(moj) = states.iter().find(|s| s.id == self.id) {
+                    let potrjen = moj.zadnji_vhod;
+                    self.nepotrjeni_vhodi.retain(|v| v.seq > potrjen);
+                    self.potrjeno_stanje = Some(moj.clone());
+                }
+                self.zadnji_tick = tick;
+                self.shrani_posnetke(tick, &states);
                 self.net_states = states;
             },
             Message::UserInfo((id, name, kills)) => {
                 pop_up_msg(format!("{} joined", name));
-                self.net_users.insert(id, UserInfo { name, kills });
+                if id == self.id {
+                    // streznik je ime morda spremenil, da je edinstveno
+                    self.user_name = name.clone();
+                }
+                let ping = self.net_users.get(&id).map_or(0, |u| u.ping);
+                self.net_users.insert(id, UserInfo { name, kills, ping });
                 self.nov_leaderboard = true;
             }
+            Message::Preimenovanje((id, name)) => {
+                if let Some(u) = self.net_users.get_mut(&id) {
+                    pop_up_msg(format!("{} is now {}", u.name, name));
+                    u.name = name.clone();
+                    self.nov_leaderboard = true;
+                }
+                if id == self.id {
+                    self.user_name = name;
+                }
+            }
             Message::Attack(new_health) => {
                 self.health = new_health;
             }
             Message::Respawn((x, y)) => {
                 self.health = 100;
-                player.nastavi_pozicijo(Vec2::new(x, y));
+                self.respawn = Some(Vec2::new(x, y));
             }
             Message::HitParticles((x, y)) => {
                 particles::spawn((x, y).into(), None, &HIT_PARTICLES);
@@ -457,6 +1647,38 @@ impl Client {
                     pop_up_msg(format!("{} killed himself", ime_umrlega));
                 }
             }
+            Message::ChatMessage((id, tekst)) => {
+                if id == ID_STREZNIKA {
+                    pop_up_msg_time(tekst, CAS_SPOROCILA);
+                } else {
+                    let ime = self.net_users.get(&id).map(|s| s.name.as_str()).unwrap_or("player");
+                    pop_up_msg_time(format!("{}: {}", ime, tekst), CAS_SPOROCILA);
+                }
+            }
+            Message::Mapa(ime) => {
+                self.nova_mapa = Some(ime);
+            }
+            Message::CakalnaVrsta(pozicija) => {
+                self.status = ClientStatus::VCakalniVrsti(pozicija);
+            }
+            Message::Restart => {
+                pop_up_msg("match restarted".to_string());
+                for u in self.net_users.values_mut() {
+                    u.kills = 0;
+                }
+                self.nov_leaderboard = true;
+            }
+            Message::Ping(seq) => {
+                self.send_msg(Message::Pong(seq));
+            }
+            Message::Pingi(pingi) => {
+                for (id, ping) in pingi {
+                    if let Some(u) = self.net_users.get_mut(&id) {
+                        u.ping = ping;
+                    }
+                }
+                self.nov_leaderboard = true;
+            }
             Message::PlayerDisconnected(id) => {
                 let ime = self.net_users.get(&id).map(|s| s.name.as_str()).unwrap_or("player");
                 pop_up_msg(format!("{} left", ime));
@@ -467,55 +1689,194 @@ impl Client {
         }
     }
 
-    pub fn recv(&mut self, player: &mut Player) {
+    pub fn recv(&mut self) {
+        if self.status == ClientStatus::PonovnoPovezovanje {
+            if Instant::now() >= self.naslednji_poskus {
+                self.poskusi_ponovno();
+            }
+            return;
+        }
+        if let ClientStatus::Prekinjen(_) = self.status {
+            return;
+        }
+
+        if let Err(e) = self.povezava.poslji_cakajoce() {
+            self.napaka.get_or_insert(NetError::Send(e));
+        }
+
+        let mut napaka = self.povezava.preberi().err().map(NetError::Recv);
+
         loop {
-            match bincode::deserialize_from::<&mut BufReader<TcpStream>, Message>(&mut self.reader) {
-                Ok(msg) => {
+            match self.povezava.naslednji_msg() {
+                Ok(Some(msg)) => {
+                    self.zadnji_prejem = Instant::now();
                     //println!("recv: {:?}", msg);
-                    self.handle_msg(msg, player);
+                    self.handle_msg(msg);
                 },
+                Ok(None) => break,
                 Err(e) => {
-                    if handle_bincode_error(e) {
-                        println!("disconnected from the server");
-                        std::process::exit(0);
-                    }
+                    napaka = Some(NetError::Recv(e));
                     break;
                 }
             }
         }
+
+        if let ClientStatus::Zavrnjen(_) = self.status {
+            return;
+        }
+        let mut napaka = self.napaka.take().or(napaka);
+        let caka_sporocila = matches!(self.status, ClientStatus::Povezan | ClientStatus::VCakalniVrsti(_));
+        if caka_sporocila && self.zadnji_prejem.elapsed() > self.timeout {
+            napaka = napaka.or(Some(NetError::Timeout(self.timeout)));
+        }
+
+        let handshake_potekel = self.status == ClientStatus::Povezovanje
+            && self.zacetek_povezovanja.elapsed() > HANDSHAKE_TIMEOUT;
+        if handshake_potekel && napaka.is_none() && self.naslov.is_some() {
+            eprintln!("err handshake timed out");
+            self.cakaj_na_poskus();
+            return;
+        }
+
+        if let Some(e) = napaka {
+            match e {
+                NetError::Recv(RecvError::Disconnect) => println!("disconnected from the server"),
+                _ => eprintln!("err server connection: {}", e),
+            }
+            if self.naslov.is_none() {
+                self.status = ClientStatus::Prekinjen(e.to_string());
+                return;
+            }
+            self.cakaj_na_poskus();
+        }
     }
 
-    pub fn narisi_cliente(&self, tekstura: &Texture2D) {
-        for state in &self.net_states {
+    fn shrani_posnetke(&mut self, tick: u32, states: &[State]) {
+        let zdaj = Instant::now();
+        // kogar ni v stanju, je mrtev ali odklopljen
+        self.posnetki.retain(|id, _| states.iter().any(|s| s.id == *id));
+
+        for state in states {
             if state.id == self.id {
-                if SHOW_COLLIDERS.get() {
-                    draw_rectangle_lines(state.position.0, state.position.1, 16.0, 28.0, 1.0, PURPLE);
-                }
                 continue;
             }
+            let posnetki = self.posnetki.entry(state.id).or_default();
+            posnetki.push_back(Posnetek { cas: zdaj, tick, state: state.clone() });
+
+            // dovolj je en posnetek pred trenutnim casom upodabljanja
+            while posnetki.len() > 2 && zdaj.duration_since(posnetki[1].cas) > self.interpolacija {
+                posnetki.pop_front();
+            }
+        }
+    }
+
+    /// posnetka okoli casa in faktor med njima, t > 1 pomeni ekstrapolacijo
+    fn poisci_posnetka(posnetki: &VecDeque<Posnetek>, cas: Instant) -> Option<(&Posnetek, &Posnetek, f32)> {
+        let zadnji = posnetki.back()?;
+        let (a, b) = match posnetki.iter().position(|p| p.cas > cas) {
+            Some(0) => return Some((&posnetki[0], &posnetki[0], 0.0)),
+            Some(i) => (&posnetki[i - 1], &posnetki[i]),
+            None if posnetki.len() < 2 => return Some((zadnji, zadnji, 0.0)),
+            None => (&posnetki[posnetki.len() - 2], zadnji),
+        };
+
+        let razmik = b.cas.duration_since(a.cas).as_secs_f32();
+        if razmik <= 0.0 {
+            return Some((b, b, 0.0));
+        }
+        let cas = cas.min(b.cas + MAX_EKSTRAPOLACIJA);
+        Some((a, b, cas.duration_since(a.cas).as_secs_f32() / razmik))
+    }
+
+    fn cas_upodabljanja(&self) -> Instant {
+        Instant::now().checked_sub(self.interpolacija).unwrap_or_else(Instant::now)
+    }
+
+    /// tick streznika, ki ga igralec trenutno vidi pri drugih igralcih
+    fn tick_pogleda(&self) -> f32 {
+        let cas = self.cas_upodabljanja();
+        self.posnetki.values()
+            .find_map(|posnetki| Client::poisci_posnetka(posnetki, cas))
+            .map(|(a, b, t)| lerp(a.tick as f32, b.tick as f32, t))
+            .unwrap_or(self.zadnji_tick as f32)
+    }
+
+    fn interpoliraj(posnetki: &VecDeque<Posnetek>, cas: Instant) -> Option<State> {
+        let (a, b, t) = Client::poisci_posnetka(posnetki, cas)?;
+
+        let zacetek = Vec2::from(a.state.position);
+        let konec = Vec2::from(b.state.position);
+        if zacetek.distance(konec) > TELEPORT_RAZDALJA {
+            return Some(b.state.clone());
+        }
+
+        let mut state = if t < 1.0 { a.state.clone() } else { b.state.clone() };
+        state.position = zacetek.lerp(konec, t).into();
+        state.rotation = lerp_kot(a.state.rotation, b.state.rotation, t);
+        state.razdalja_meca = lerp(a.state.razdalja_meca, b.state.razdalja_meca, t);
+        Some(state)
+    }
+
+    pub fn narisi_cliente(&self, tekstura: &Texture2D) {
+        if SHOW_COLLIDERS.get() {
+            if let Some(state) = self.net_states.iter().find(|s| s.id == self.id) {
+                draw_rectangle_lines(state.position.0, state.position.1, 16.0, 28.0, 1.0, PURPLE);
+            }
+        }
+
+        let cas = self.cas_upodabljanja();
+        for posnetki in self.posnetki.values() {
+            let Some(state) = Client::interpoliraj(posnetki, cas) else { continue };
             //draw_rectangle_lines(state.position.0, state.position.1, 16.0, 28.0, 1.0, macroquad::color::RED);
             let name = self.net_users.get(&state.id).map(|u| u.name.as_str()).unwrap_or("player");
             Player::narisi_iz(tekstura, state.position.into(), state.anim_frame.into(), state.rotation, state.razdalja_meca, state.attack_time, name, -1);
         }
     }
 
-    pub fn get_leaderboard_data(&self) -> Vec<(String, i32)> {
+    /// id igralcev, ki so trenutno vidni, urejeni po velikosti
+    pub fn vidni_igralci(&self) -> Vec<u32> {
+        let mut idji: Vec<u32> = self.posnetki.keys().copied().collect();
+        idji.sort();
+        idji
+    }
+
+    /// pozicija, kot je narisana v narisi_cliente
+    pub fn pozicija_igralca(&self, id: u32) -> Option<Vec2> {
+        let state = Client::interpoliraj(self.posnetki.get(&id)?, self.cas_upodabljanja())?;
+        Some(state.position.into())
+    }
+
+    pub fn ime_igralca(&self, id: u32) -> &str {
+        self.net_users.get(&id).map(|u| u.name.as_str()).unwrap_or("player")
+    }
+
+    pub fn get_leaderboard_data(&self) -> Vec<(String, i32, u16)> {
         let mut vec = Vec::new();
 
-        for (_id, info) in &self.net_users {
-            vec.push((info.name.clone(), info.kills));
+        for info in self.net_users.values() {
+            vec.push((info.name.clone(), info.kills, info.ping));
         }
 
-        vec.sort_by(|a, b| b.1.cmp(&a.1));
+        vec.sort_by_key(|a| std::cmp::Reverse(a.1));
         vec
     }
 }
 
 pub enum NetInterface {
-    Server(Server),
+    /// gostitelj ima streznik in lokalnega odjemalca
+    Server(Box<Server>, Client),
     Client(Client),
 }
 
+impl NetInterface {
+    pub fn client(&mut self) -> &mut Client {
+        match self {
+            NetInterface::Server(_, client) => client,
+            NetInterface::Client(client) => client,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct State {
     pub id: u32,
@@ -524,18 +1885,50 @@ pub struct State {
     pub anim_frame: (f32, f32),
     pub attack_time: f32,
     pub razdalja_meca: f32,
+    /// seq zadnjega vhoda, ki ga je streznik ze simuliral
+    pub zadnji_vhod: u32,
+    pub gibanje: StanjeGibanja,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Message {
-    DodeljenId(u32),
+    // handshake mora ostati na zacetku in se ne sme spreminjati, da ga razumejo tudi druge verzije
+    Hello { protocol_version: u32, game_version: String },
+    Welcome { id: u32, token: u64 },
+    Reject { reason: String },
+    /// poslje se takoj za Hello, streznik jo prebere sele, ko je verzija preverjena
+    /// token je od prejsnje povezave, ce se igralec vraca
+    /// gledalec dobi vsa stanja in dogodke, a ne igra
+    Prijava { token: Option<u64>, geslo: Option<String>, gledalec: bool },
+    /// streznik je poln, pozicija v cakalni vrsti, Welcome pride, ko se sprosti mesto
+    CakalnaVrsta(u32),
     UserInfo((u32, String, i32)),
-    PlayerState(State),
-    AllPlayersState(Vec<State>),
+    /// id in novo ime igralca
+    Preimenovanje((u32, String)),
+    /// potrjen_tick je zadnji prejeti posnetek
+    PlayerInput { potrjen_tick: u32, vhodi: Vec<PlayerInput> },
+    /// stanja igralcev, zakodirana kot razlika od posnetka osnova (0 pomeni brez osnove)
+    Posnetek { tick: u32, osnova: u32, podatki: Vec<u8> },
     Attack(i32),
     Respawn((f32, f32)),
     HitParticles((f32, f32)),
     PlayerDied((u32, u32)),
     PlayerDisconnected(u32),
+    /// id posiljatelja in besedilo, streznik id od odjemalca zamenja z id povezave
+    ChatMessage((u32, String)),
+    /// ime mape, ki jo igrajo vsi
+    Mapa(String),
+    /// uboji so ponastavljeni, igralci dobijo se Respawn
+    Restart,
+    Ping(u32),
+    Pong(u32),
+    /// id in ping v milisekundah za vse igralce
+    Pingi(Vec<(u32, u16)>),
 }
 
+impl Message {
+    /// stanja se posiljajo vsak frame, zato jih ni treba ponovno posiljati
+    pub fn zanesljivo(&self) -> bool {
+        !matches!(self, Message::PlayerInput { .. } | Message::Posnetek { .. } | Message::Ping(_) | Message::Pong(_) | Message::Pingi(_))
+    }
+}
@@ -1,4 +1,5 @@
 use macroquad::prelude::*;
+use std::f32::consts::PI;
 use std::cell::Cell;
 use std::collections::VecDeque;
 use crate::{physics, AABB, StaticenAABBRef};
@@ -14,9 +15,12 @@ pub fn screen_units_height() -> f32 {
     128.0
 }
 
+/// zgornji levi kot mape v svetu
+pub const MAP_POS: Vec2 = Vec2::new(-256.0, -128.0);
+
 thread_local! {
-    pub static KAMERA_POS: Cell<Vec2> = Cell::new(Vec2::ZERO);
-    pub static SHOW_COLLIDERS: Cell<bool> = Cell::new(false);
+    pub static KAMERA_POS: Cell<Vec2> = const { Cell::new(Vec2::ZERO) };
+    pub static SHOW_COLLIDERS: Cell<bool> = const { Cell::new(false) };
 }
 
 pub fn posodobi_kamero() {
@@ -60,6 +64,12 @@ pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a * (1.0 - t) + b * t
 }
 
+/// interpolira kot po krajsi poti
+pub fn lerp_kot(a: f32, b: f32, t: f32) -> f32 {
+    let razlika = (b - a + PI).rem_euclid(2.0 * PI) - PI;
+    a + razlika * t
+}
+
 pub fn lerp_color(a: Color, b: Color, t: f32) -> Color {
     Color::new(
         a.r * (1.0 - t) + b.r * t,
@@ -105,6 +115,12 @@ pub fn generate_map_colliders(map_image: Image, offset: Vec2) -> Vec<StaticenAAB
     colliders
 }
 
+/// mape so slike v assets, ime mape je ime datoteke brez .png
+pub fn pot_mape(ime: &str) -> Option<String> {
+    let veljavno = !ime.is_empty() && ime.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    veljavno.then(|| format!("assets/{}.png", ime))
+}
+
 static mut MESSAGES: VecDeque<PopUpMessage> = VecDeque::new();
 
 struct PopUpMessage {
@@ -118,12 +134,12 @@ pub fn pop_up_msg(msg: String) {
 }
 
 pub fn pop_up_msg_time(msg: String, time: f32) {
-    let messages = unsafe { &mut MESSAGES };
+    let messages = unsafe { &mut *std::ptr::addr_of_mut!(MESSAGES) };
     messages.push_front(PopUpMessage { time, total_time: time, msg });
 }
 
 pub fn narisi_pop_up_messages(delta: f32) {
-    let messages = unsafe { &mut MESSAGES };
+    let messages = unsafe { &mut *std::ptr::addr_of_mut!(MESSAGES) };
 
     let mut y = screen_units_height() - 5.0;
 
@@ -168,7 +184,7 @@ pub fn narisi_tekst_sredinsko(tekst: &str, pos: Vec2, scale: f32) {
     });
 }
 
-pub fn narisi_leaderboard(data: &Vec<(String, i32)>) {
+pub fn narisi_leaderboard(data: &[(String, i32, u16)]) {
     let scr_w = screen_units_width();
     let scr_h = screen_units_height();
     let kamera = KAMERA_POS.get();
@@ -179,9 +195,46 @@ pub fn narisi_leaderboard(data: &Vec<(String, i32)>) {
     narisi_tekst_sredinsko("LEADERBOARD", pos, 0.75);
     pos.y += 15.0;
 
-    for i in 0..data.len() {
-        narisi_tekst_sredinsko(&format!("{}. {}: {}", (i+1), data[i].0, data[i].1), pos, 0.4);
+    for (i, (ime, kills, ping)) in data.iter().enumerate() {
+        narisi_tekst_sredinsko(&format!("{}. {}: {} ({} ms)", (i+1), ime, kills, ping), pos, 0.4);
+        pos.y += 10.0;
+    }
+}
+
+/// seznam vrstic, izbrana je oznacena, npr. za brskalnik streznikov
+pub fn narisi_seznam(naslov: &str, vrstice: &[String], izbrana: usize) {
+    posodobi_kamero();
+    clear_background(Color::new(0.1, 0.1, 0.1, 1.0));
+
+    let mut pos = vec2(0.0, -screen_units_height() + 40.0) + KAMERA_POS.get();
+    narisi_tekst_sredinsko(naslov, pos, 0.75);
+    pos.y += 20.0;
+
+    for (i, vrstica) in vrstice.iter().enumerate() {
+        let vrstica = if i == izbrana { format!("> {} <", vrstica) } else { vrstica.clone() };
+        narisi_tekst_sredinsko(&vrstica, pos, 0.4);
         pos.y += 10.0;
     }
 }
 
+/// zatemni igro in napise naslov in sporocilo, npr. med ponovnim povezovanjem
+pub fn narisi_prekrivalo(naslov: &str, sporocilo: &str) {
+    let scr_w = screen_units_width();
+    let scr_h = screen_units_height();
+    let kamera = KAMERA_POS.get();
+
+    draw_rectangle(-scr_w + kamera.x, -scr_h + kamera.y, 2.0 * scr_w, 2.0 * scr_h, Color::new(0.0, 0.0, 0.0, 0.5));
+    narisi_tekst_sredinsko(naslov, kamera - vec2(0.0, 10.0), 0.75);
+    narisi_tekst_sredinsko(sporocilo, kamera + vec2(0.0, 5.0), 0.4);
+}
+
+
+/// cez cel zaslon napise naslov in sporocilo, npr. ko streznik zavrne povezavo
+pub fn narisi_obvestilo(naslov: &str, sporocilo: &str) {
+    posodobi_kamero();
+    clear_background(Color::new(0.1, 0.1, 0.1, 1.0));
+
+    let pos = KAMERA_POS.get();
+    narisi_tekst_sredinsko(naslov, pos - vec2(0.0, 10.0), 0.75);
+    narisi_tekst_sredinsko(sporocilo, pos + vec2(0.0, 5.0), 0.4);
+}
@@ -45,7 +45,7 @@ pub fn init() {
 }
 
 pub fn spawn(position: Vec2, direction: Option<Vec2>, p: &ParticleParams) {
-    let state = unsafe { STATE.as_mut().unwrap() };
+    let state = unsafe { (*std::ptr::addr_of_mut!(STATE)).as_mut().unwrap() };
 
     let amount = rand::gen_range(p.amount.start, p.amount.end - 1);
 
@@ -79,7 +79,7 @@ pub fn spawn(position: Vec2, direction: Option<Vec2>, p: &ParticleParams) {
 }
 
 pub fn narisi(delta: f32) {
-    let state = unsafe { STATE.as_mut().unwrap() };
+    let state = unsafe { (*std::ptr::addr_of_mut!(STATE)).as_mut().unwrap() };
 
     let mut to_delete = None;
 
@@ -1,15 +1,26 @@
 use std::f32::consts::PI;
 
 use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
 
 use crate::{texture_params_source, DinamicenAABBRef, physics, AABB, pozicija_miske_v_svetu, KAMERA_POS, lerp, SHOW_COLLIDERS, particles, pop_up_msg};
-use crate::{LAYER_MAP, LAYER_PLAYER, LAYER_SWORD};
+use crate::{LAYER_MAP, LAYER_LOKALNI, LAYER_LOKALNI_MEC};
 use crate::particles::{JUMP_PARTICLES, COOL_PARTICLES};
 
 const PLAYER_SPEED: f32 = 75.0;
 const JUMP_VEL: f32 = 500.0;
 const MAX_VEL: f32 = 600.0;
 const GRAVITY: f32 = 1500.0;
+/// najdaljsi frame, ki ga se simuliramo naenkrat
+pub const MAX_DELTA: f32 = 1.0 / 15.0;
+/// najkrajsi korak, ki ga streznik sprejme, pri krajsem bi igralec obvisel v zraku
+pub const MIN_DELTA: f32 = 1.0 / 240.0;
+/// korak simulacije, enak na odjemalcu in strezniku
+pub const TICK_TIME: f32 = 1.0 / 60.0;
+/// kako dalec od igralca je lahko mec
+pub const MAX_DOSEG: f32 = 26.0;
+/// po napadu mora miniti toliko casa, preden lahko igralec spet napade
+pub const CAS_NAPADA: f32 = 0.3;
 
 pub struct Animacija {
     pub cas: f32,
@@ -54,41 +65,73 @@ impl Animacija {
     }
 }
 
-pub struct Player {
-    pub position: Vec2,
-    pub rotation: f32,
-    pub ime: String,
+/// vhod igralca v enem framu, odjemalec ga poslje strezniku
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct PlayerInput {
+    pub seq: u32,
+    pub delta: f32,
+    /// -1 levo, 1 desno
+    pub premik: f32,
+    pub skok: bool,
+    pub napad: bool,
+    /// smer proti miski
+    pub kot: f32,
+    /// razdalja do miske, omejena na MAX_DOSEG
+    pub doseg: f32,
+    /// tick streznika, ki ga je igralec videl pri drugih igralcih
+    pub pogled: f32,
+}
 
-    pub health: i32,
+impl PlayerInput {
+    /// NaN in neskoncnost bi zaobsli omejitve, ker clamp NaN pusti pri miru
+    pub fn veljaven(&self) -> bool {
+        [self.delta, self.premik, self.kot, self.doseg, self.pogled].iter().all(|v| v.is_finite())
+    }
+}
 
+/// kaj se je zgodilo v enem koraku simulacije
+pub struct Korak {
+    pub skocil: bool,
+    pub premik: Vec2,
+}
+
+/// del stanja simulacije, ki ga State nima, a ga odjemalec potrebuje za popravek napovedi
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct StanjeGibanja {
+    /// pozicija na zacetku zadnjega koraka
+    pub zacetna_pozicija: (f32, f32),
+    pub pozicija_meca: (f32, f32),
+    pub velocity_y: f32,
+    pub jumps_allowed: i32,
+}
+
+/// premikanje igralca, ki ga na enak nacin simulirata odjemalec in streznik
+pub struct Gibanje {
+    pub position: Vec2,
+    pub rotation: f32,
     velocity_y: f32,
     jumps_allowed: i32,
     pub attack_time: f32,
+    pub razdalja_meca: f32,
 
-    pub texture: Texture2D,
     aabb_ref: DinamicenAABBRef,
-
     sword_ref: DinamicenAABBRef,
-    pub razdalja_meca: f32,
 
     pub animacije: Vec<Animacija>,
     pub trenutna_anim: usize,
 }
 
-impl Player {
-    pub fn new(ime: String, position: Vec2, texture: Texture2D) -> Player {
-        Player {
+impl Gibanje {
+    pub fn new(position: Vec2, layer: u32, layer_meca: u32, user_id: u32) -> Gibanje {
+        Gibanje {
             position,
             rotation: 0.0,
-            ime,
-            health: 100,
             velocity_y: 0.0,
             jumps_allowed: 0,
             attack_time: 99.0,
-            texture,
-            aabb_ref: physics::dodaj_dinamicen_obj(AABB::from_vec(position, vec2(16.0, 28.0)), LAYER_PLAYER, LAYER_PLAYER | LAYER_MAP, 0),
-            sword_ref: physics::dodaj_dinamicen_obj(AABB::from_vec(position, vec2(10.0, 10.0)), LAYER_SWORD, LAYER_SWORD | LAYER_MAP, 10),
             razdalja_meca: 0.0,
+            aabb_ref: physics::dodaj_dinamicen_obj(AABB::from_vec(position, vec2(16.0, 28.0)), layer, layer | LAYER_MAP, user_id),
+            sword_ref: physics::dodaj_dinamicen_obj(AABB::from_vec(position, vec2(10.0, 10.0)), layer_meca, layer_meca | LAYER_MAP, user_id),
             animacije: vec![
                 Animacija::new(Rect::new(0.0, 32.0, 32.0, 32.0), 2, 0.350, true), // idle 0
                 Animacija::new(Rect::new(0.0, 64.0, 32.0, 32.0), 4, 0.100, true), // walk 1
@@ -97,10 +140,8 @@ impl Player {
         }
     }
 
-    pub fn posodobi(&mut self, delta: f32) {
-        if self.health <= 0 {
-            return;
-        }
+    pub fn simuliraj(&mut self, vhod: &PlayerInput) -> Korak {
+        let delta = vhod.delta.clamp(MIN_DELTA, MAX_DELTA);
 
         let nova_pozicija = physics::pozicija_obj(&self.aabb_ref);
         let mut is_grounded = false;
@@ -112,23 +153,13 @@ impl Player {
         self.position = nova_pozicija;
 
         let mut premik = Vec2::ZERO;
+        premik.x = vhod.premik.clamp(-1.0, 1.0) * PLAYER_SPEED * delta;
 
-        if is_key_down(KeyCode::A) {
-            premik.x -= PLAYER_SPEED * delta;
-        }
-        if is_key_down(KeyCode::D) {
-            premik.x += PLAYER_SPEED * delta;
-        }
-
-        if is_key_pressed(KeyCode::P) {
-            particles::spawn(self.position + vec2(8.0, 14.0), None, &COOL_PARTICLES);
-            pop_up_msg("kako kul ?!?!?!".to_string());
-        }
-
-        if (self.jumps_allowed > 0 || is_grounded) && is_key_pressed(KeyCode::W) {
+        let mut skocil = false;
+        if (self.jumps_allowed > 0 || is_grounded) && vhod.skok {
             self.velocity_y = -JUMP_VEL;
-            particles::spawn(self.position + vec2(8.0, 28.0), Some(vec2(0.0, 0.5)), &JUMP_PARTICLES);
-            if is_grounded == false {
+            skocil = true;
+            if !is_grounded {
                 self.jumps_allowed -= 1;
             }
         }
@@ -140,27 +171,20 @@ impl Player {
         premik.y += self.velocity_y * delta;
 
         physics::premakni_obj(&self.aabb_ref, premik);
+        physics::resi_trke_obj(&self.aabb_ref);
 
-        let smer_meca = pozicija_miske_v_svetu() - (self.position + vec2(8.0, 12.0));
-        let zeljena_pozicija = smer_meca.clamp_length_max(26.0) + self.position + vec2(3.0, 7.0);
+        let smer_meca = Vec2::from_angle(vhod.kot) * vhod.doseg.clamp(0.0, MAX_DOSEG);
+        let zeljena_pozicija = smer_meca + self.position + vec2(3.0, 7.0);
         let pozicija_meca = physics::pozicija_obj(&self.sword_ref);
         let premik_meca = zeljena_pozicija - pozicija_meca;
         physics::premakni_obj(&self.sword_ref, premik_meca * 10.0 * delta);
+        physics::resi_trke_obj(&self.sword_ref);
 
         let dejanska_smer_meca = (pozicija_meca + vec2(5.0, 5.0)) - (self.position + vec2(8.0, 12.0));
         self.rotation = f32::atan2(dejanska_smer_meca.y, dejanska_smer_meca.x);
         self.razdalja_meca = dejanska_smer_meca.length() - 3.0;
 
-        let zeljena_pozicija_kamere = Vec2::lerp(self.position, pozicija_miske_v_svetu(), 0.1);
-        let pozicija_kamere = KAMERA_POS.get();
-        // zelim pocasnejse premikanje kamere na y
-        let nova_pozicija = vec2(
-            lerp(pozicija_kamere.x, zeljena_pozicija_kamere.x, 10.0 * delta),
-            lerp(pozicija_kamere.y, zeljena_pozicija_kamere.y, 3.0 * delta)
-        );
-        KAMERA_POS.set(nova_pozicija);
-
-        if is_mouse_button_pressed(MouseButton::Left) {
+        if vhod.napad && self.attack_time >= CAS_NAPADA {
             self.attack_time = 0.0;
         } else {
             self.attack_time += delta;
@@ -172,20 +196,158 @@ impl Player {
         } else {
             self.trenutna_anim = 0;
         }
+
+        Korak { skocil, premik }
+    }
+
+    pub fn get_anim(&self) -> &Animacija {
+        &self.animacije[self.trenutna_anim]
+    }
+
+    /// pozicija po resevanju trkov, position je pozicija na zacetku zadnjega koraka
+    pub fn trenutna_pozicija(&self) -> Vec2 {
+        physics::pozicija_obj(&self.aabb_ref)
+    }
+
+    pub fn stanje(&self) -> StanjeGibanja {
+        StanjeGibanja {
+            zacetna_pozicija: self.position.into(),
+            pozicija_meca: physics::pozicija_obj(&self.sword_ref).into(),
+            velocity_y: self.velocity_y,
+            jumps_allowed: self.jumps_allowed,
+        }
+    }
+
+    /// prevzame stanje, ki ga je izracunal streznik
+    pub fn ponastavi(&mut self, pozicija: Vec2, attack_time: f32, stanje: &StanjeGibanja) {
+        self.position = stanje.zacetna_pozicija.into();
+        self.velocity_y = stanje.velocity_y;
+        self.jumps_allowed = stanje.jumps_allowed;
+        self.attack_time = attack_time;
+        physics::premakni_obj_na(&self.aabb_ref, pozicija);
+        physics::premakni_obj_na(&self.sword_ref, stanje.pozicija_meca.into());
+    }
+
+    pub fn nastavi_pozicijo(&mut self, position: Vec2) {
+        self.position = position;
+        self.velocity_y = 0.0;
+        physics::premakni_obj_na(&self.aabb_ref, position);
+        physics::premakni_obj_na(&self.sword_ref, position);
+    }
+}
+
+pub struct Player {
+    pub ime: String,
+    pub health: i32,
+    pub texture: Texture2D,
+    pub gibanje: Gibanje,
+    naslednji_seq: u32,
+    /// pritiski od zadnjega ticka, da se ne izgubijo, ce frame nima ticka
+    cakajoc_skok: bool,
+    cakajoc_napad: bool,
+    /// npr. med pisanjem v klepet tipke ne premikajo igralca
+    pub vhod_onemogocen: bool,
+}
+
+impl Player {
+    pub fn new(ime: String, position: Vec2, texture: Texture2D) -> Player {
+        Player {
+            ime,
+            health: 100,
+            texture,
+            gibanje: Gibanje::new(position, LAYER_LOKALNI, LAYER_LOKALNI_MEC, 0),
+            naslednji_seq: 1,
+            cakajoc_skok: false,
+            cakajoc_napad: false,
+            vhod_onemogocen: false,
+        }
+    }
+
+    fn preberi_vhod(&mut self, delta: f32) -> PlayerInput {
+        let mut premik = 0.0;
+        if is_key_down(KeyCode::A) && !self.vhod_onemogocen {
+            premik -= 1.0;
+        }
+        if is_key_down(KeyCode::D) && !self.vhod_onemogocen {
+            premik += 1.0;
+        }
+
+        let smer = pozicija_miske_v_svetu() - (self.gibanje.position + vec2(8.0, 12.0));
+
+        let seq = self.naslednji_seq;
+        self.naslednji_seq += 1;
+
+        PlayerInput {
+            seq,
+            delta,
+            premik,
+            skok: std::mem::take(&mut self.cakajoc_skok),
+            napad: std::mem::take(&mut self.cakajoc_napad),
+            kot: f32::atan2(smer.y, smer.x),
+            doseg: smer.length().min(MAX_DOSEG),
+            pogled: 0.0,
+        }
+    }
+
+    /// klice se vsak frame, ker tick ni nujno v vsakem framu
+    pub fn zabelezi_pritiske(&mut self) {
+        if self.vhod_onemogocen {
+            return;
+        }
+
+        self.cakajoc_skok |= is_key_pressed(KeyCode::W);
+        self.cakajoc_napad |= is_mouse_button_pressed(MouseButton::Left);
+
+        if is_key_pressed(KeyCode::P) && self.health > 0 {
+            particles::spawn(self.gibanje.position + vec2(8.0, 14.0), None, &COOL_PARTICLES);
+            pop_up_msg("kako kul ?!?!?!".to_string());
+        }
+    }
+
+    /// en tick simulacije, vrne vhod, ki ga je treba poslati strezniku
+    pub fn posodobi(&mut self, delta: f32) -> Option<PlayerInput> {
+        if self.health <= 0 {
+            return None;
+        }
+
+        let vhod = self.preberi_vhod(delta);
+        let korak = self.gibanje.simuliraj(&vhod);
+
+        if korak.skocil {
+            particles::spawn(self.gibanje.position + vec2(8.0, 28.0), Some(vec2(0.0, 0.5)), &JUMP_PARTICLES);
+        }
+
+        Some(vhod)
+    }
+
+    pub fn premakni_kamero(&self, delta: f32) {
+        if self.health <= 0 {
+            return;
+        }
+
+        let zeljena_pozicija_kamere = Vec2::lerp(self.gibanje.position, pozicija_miske_v_svetu(), 0.1);
+        let pozicija_kamere = KAMERA_POS.get();
+        // zelim pocasnejse premikanje kamere na y
+        let nova_pozicija = vec2(
+            lerp(pozicija_kamere.x, zeljena_pozicija_kamere.x, 10.0 * delta),
+            lerp(pozicija_kamere.y, zeljena_pozicija_kamere.y, 3.0 * delta)
+        );
+        KAMERA_POS.set(nova_pozicija);
     }
 
     pub fn narisi(&self) {
         if self.health <= 0 {
             return;
         }
-        let position = physics::pozicija_obj(&self.aabb_ref);
-        Player::narisi_iz(&self.texture, position, self.get_anim().izr_frame_xy(), self.rotation, self.razdalja_meca, self.attack_time, &self.ime, self.health);
+        let g = &self.gibanje;
+        let position = physics::pozicija_obj(&g.aabb_ref);
+        Player::narisi_iz(&self.texture, position, g.get_anim().izr_frame_xy(), g.rotation, g.razdalja_meca, g.attack_time, &self.ime, self.health);
     }
 
     pub fn narisi_iz(tekstura: &Texture2D, position: Vec2, anim_frame_xy: Vec2, rotacija: f32, razdalja_meca: f32, attack_time: f32, ime: &str, health: i32) {
         let draw_position = position - vec2(8.0, 4.0);
         let mut params = texture_params_source(anim_frame_xy.x, anim_frame_xy.y, 32.0, 32.0);
-        params.flip_x = rotacija > PI / 2.0 || rotacija < -PI / 2.0;
+        params.flip_x = !(-PI / 2.0..=PI / 2.0).contains(&rotacija);
         draw_texture_ex(tekstura, draw_position.x, draw_position.y, WHITE, params);
 
         let attack_amount = -f32::powi(attack_time / 0.3 - 1.0, 3);
@@ -215,10 +377,6 @@ impl Player {
         }
     }
 
-    pub fn get_anim(&self) -> &Animacija {
-        &self.animacije[self.trenutna_anim]
-    }
-
     pub fn calc_sword_hitbox(player_pos: Vec2, attack_time: f32, razdalja_meca: f32, rotacija: f32) -> AABB {
         let center = player_pos - vec2(8.0, 4.0) + vec2(16.0, 16.0);
 
@@ -231,9 +389,24 @@ impl Player {
     }
 
     pub fn nastavi_pozicijo(&mut self, position: Vec2) {
-        self.position = position;
-        physics::premakni_obj_na(&self.aabb_ref, position);
-        physics::premakni_obj_na(&self.sword_ref, position);
+        self.gibanje.nastavi_pozicijo(position);
+    }
+
+    /// previje na stanje streznika in ponovno simulira vhode, ki jih se ni potrdil
+    pub fn uskladi<'a>(&mut self, pozicija: Vec2, attack_time: f32, stanje: &StanjeGibanja, nepotrjeni: impl Iterator<Item = &'a PlayerInput>) {
+        // animacija je ze predvajana, ponovna simulacija je ne sme pospesiti
+        let trenutna_anim = self.gibanje.trenutna_anim;
+        let casi_anim: Vec<f32> = self.gibanje.animacije.iter().map(|a| a.cas).collect();
+
+        self.gibanje.ponastavi(pozicija, attack_time, stanje);
+        for vhod in nepotrjeni {
+            self.gibanje.simuliraj(vhod);
+        }
+
+        self.gibanje.trenutna_anim = trenutna_anim;
+        for (anim, cas) in self.gibanje.animacije.iter_mut().zip(casi_anim) {
+            anim.cas = cas;
+        }
     }
 }
 
@@ -0,0 +1,323 @@
+use crate::{State, StanjeGibanja};
+
+/// koliko posnetkov si zapomnita streznik in odjemalec kot osnovo za razlike
+pub const ZGODOVINA_POSNETKOV: usize = 64;
+
+const KORAK_POZICIJE: f32 = 1.0 / 8.0;
+const KORAK_HITROSTI: f32 = 1.0 / 16.0;
+const KORAK_NAPADA: f32 = 1.0 / 100.0;
+const KORAK_RAZDALJE: f32 = 1.0 / 4.0;
+/// frami animacij so na mrezi 32x32
+const KORAK_ANIM: f32 = 32.0;
+
+// kateri deli stanja so poslani, ostali so enaki kot v osnovi
+const SPREMEMBA_X: u8 = 1 << 0;
+const SPREMEMBA_Y: u8 = 1 << 1;
+const SPREMEMBA_ROTACIJA: u8 = 1 << 2;
+const SPREMEMBA_ANIM: u8 = 1 << 3;
+const SPREMEMBA_NAPAD: u8 = 1 << 4;
+const SPREMEMBA_RAZDALJA: u8 = 1 << 5;
+/// samo za lastnika stanja
+const SPREMEMBA_VHOD: u8 = 1 << 6;
+/// samo za lastnika stanja
+const SPREMEMBA_GIBANJE: u8 = 1 << 7;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct KvantiziranoGibanje {
+    zacetni_x: i32,
+    zacetni_y: i32,
+    mec_x: i32,
+    mec_y: i32,
+    velocity_y: i32,
+    jumps_allowed: u8,
+}
+
+/// State, kot ga vidi mreza
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KvantiziranoStanje {
+    pub id: u32,
+    x: i32,
+    y: i32,
+    rotacija: u16,
+    anim: (u8, u8),
+    attack_time: u8,
+    razdalja_meca: u8,
+    zadnji_vhod: u32,
+    gibanje: KvantiziranoGibanje,
+}
+
+fn kvantiziraj(v: f32, korak: f32) -> i32 {
+    (v / korak).round() as i32
+}
+
+fn kvantiziraj_u8(v: f32, korak: f32) -> u8 {
+    (v / korak).round().clamp(0.0, u8::MAX as f32) as u8
+}
+
+fn kvantiziraj_kot(kot: f32) -> u16 {
+    let del = kot.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU;
+    (del * 65536.0).round() as u32 as u16
+}
+
+fn kot_iz(kot: u16) -> f32 {
+    let kot = kot as f32 / 65536.0 * std::f32::consts::TAU;
+    if kot > std::f32::consts::PI { kot - std::f32::consts::TAU } else { kot }
+}
+
+impl KvantiziranoStanje {
+    pub fn iz(state: &State) -> KvantiziranoStanje {
+        let g = &state.gibanje;
+        KvantiziranoStanje {
+            id: state.id,
+            x: kvantiziraj(state.position.0, KORAK_POZICIJE),
+            y: kvantiziraj(state.position.1, KORAK_POZICIJE),
+            rotacija: kvantiziraj_kot(state.rotation),
+            anim: (kvantiziraj_u8(state.anim_frame.0, KORAK_ANIM), kvantiziraj_u8(state.anim_frame.1, KORAK_ANIM)),
+            attack_time: kvantiziraj_u8(state.attack_time, KORAK_NAPADA),
+            razdalja_meca: kvantiziraj_u8(state.razdalja_meca, KORAK_RAZDALJE),
+            zadnji_vhod: state.zadnji_vhod,
+            gibanje: KvantiziranoGibanje {
+                zacetni_x: kvantiziraj(g.zacetna_pozicija.0, KORAK_POZICIJE),
+                zacetni_y: kvantiziraj(g.zacetna_pozicija.1, KORAK_POZICIJE),
+                mec_x: kvantiziraj(g.pozicija_meca.0, KORAK_POZICIJE),
+                mec_y: kvantiziraj(g.pozicija_meca.1, KORAK_POZICIJE),
+                velocity_y: kvantiziraj(g.velocity_y, KORAK_HITROSTI),
+                jumps_allowed: g.jumps_allowed.clamp(0, u8::MAX as i32) as u8,
+            },
+        }
+    }
+
+    pub fn v_state(&self) -> State {
+        let g = &self.gibanje;
+        State {
+            id: self.id,
+            position: (self.x as f32 * KORAK_POZICIJE, self.y as f32 * KORAK_POZICIJE),
+            rotation: kot_iz(self.rotacija),
+            anim_frame: (self.anim.0 as f32 * KORAK_ANIM, self.anim.1 as f32 * KORAK_ANIM),
+            attack_time: self.attack_time as f32 * KORAK_NAPADA,
+            razdalja_meca: self.razdalja_meca as f32 * KORAK_RAZDALJE,
+            zadnji_vhod: self.zadnji_vhod,
+            gibanje: StanjeGibanja {
+                zacetna_pozicija: (g.zacetni_x as f32 * KORAK_POZICIJE, g.zacetni_y as f32 * KORAK_POZICIJE),
+                pozicija_meca: (g.mec_x as f32 * KORAK_POZICIJE, g.mec_y as f32 * KORAK_POZICIJE),
+                velocity_y: g.velocity_y as f32 * KORAK_HITROSTI,
+                jumps_allowed: g.jumps_allowed as i32,
+            },
+        }
+    }
+}
+
+fn zapisi_varint(buf: &mut Vec<u8>, mut v: u32) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+/// razlika, zapisana zigzag, da so majhne negativne vrednosti kratke
+fn zapisi_razliko(buf: &mut Vec<u8>, nova: i32, stara: i32) {
+    let r = nova.wrapping_sub(stara);
+    zapisi_varint(buf, ((r << 1) ^ (r >> 31)) as u32);
+}
+
+struct Bralnik<'a> {
+    podatki: &'a [u8],
+}
+
+impl Bralnik<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let (prvi, ostalo) = self.podatki.split_first()?;
+        self.podatki = ostalo;
+        Some(*prvi)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn varint(&mut self) -> Option<u32> {
+        let mut v: u32 = 0;
+        for i in 0..5 {
+            let b = self.u8()?;
+            v |= ((b & 0x7f) as u32) << (7 * i);
+            if b & 0x80 == 0 {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    fn razlika(&mut self, stara: i32) -> Option<i32> {
+        let z = self.varint()?;
+        let r = ((z >> 1) as i32) ^ -((z & 1) as i32);
+        Some(stara.wrapping_add(r))
+    }
+}
+
+fn najdi_osnovo(osnova: &[KvantiziranoStanje], id: u32) -> KvantiziranoStanje {
+    osnova.iter()
+        .find(|s| s.id == id)
+        .copied()
+        .unwrap_or(KvantiziranoStanje { id, ..Default::default() })
+}
+
+/// zakodira stanja kot razliko od osnove, ki jo ima odjemalec z id lastnik
+pub fn zakodiraj(stanja: &[KvantiziranoStanje], osnova: &[KvantiziranoStanje], lastnik: u32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    zapisi_varint(&mut buf, stanja.len() as u32);
+
+    for s in stanja {
+        let o = najdi_osnovo(osnova, s.id);
+
+        let mut spremembe = 0;
+        if s.x != o.x { spremembe |= SPREMEMBA_X; }
+        if s.y != o.y { spremembe |= SPREMEMBA_Y; }
+        if s.rotacija != o.rotacija { spremembe |= SPREMEMBA_ROTACIJA; }
+        if s.anim != o.anim { spremembe |= SPREMEMBA_ANIM; }
+        if s.attack_time != o.attack_time { spremembe |= SPREMEMBA_NAPAD; }
+        if s.razdalja_meca != o.razdalja_meca { spremembe |= SPREMEMBA_RAZDALJA; }
+        if s.id == lastnik {
+            if s.zadnji_vhod != o.zadnji_vhod { spremembe |= SPREMEMBA_VHOD; }
+            if s.gibanje != o.gibanje { spremembe |= SPREMEMBA_GIBANJE; }
+        }
+
+        zapisi_varint(&mut buf, s.id);
+        buf.push(spremembe);
+
+        if spremembe & SPREMEMBA_X != 0 { zapisi_razliko(&mut buf, s.x, o.x); }
+        if spremembe & SPREMEMBA_Y != 0 { zapisi_razliko(&mut buf, s.y, o.y); }
+        if spremembe & SPREMEMBA_ROTACIJA != 0 { buf.extend_from_slice(&s.rotacija.to_le_bytes()); }
+        if spremembe & SPREMEMBA_ANIM != 0 { buf.extend_from_slice(&[s.anim.0, s.anim.1]); }
+        if spremembe & SPREMEMBA_NAPAD != 0 { buf.push(s.attack_time); }
+        if spremembe & SPREMEMBA_RAZDALJA != 0 { buf.push(s.razdalja_meca); }
+        if spremembe & SPREMEMBA_VHOD != 0 { zapisi_razliko(&mut buf, s.zadnji_vhod as i32, o.zadnji_vhod as i32); }
+        if spremembe & SPREMEMBA_GIBANJE != 0 {
+            let (g, og) = (&s.gibanje, &o.gibanje);
+            zapisi_razliko(&mut buf, g.zacetni_x, og.zacetni_x);
+            zapisi_razliko(&mut buf, g.zacetni_y, og.zacetni_y);
+            zapisi_razliko(&mut buf, g.mec_x, og.mec_x);
+            zapisi_razliko(&mut buf, g.mec_y, og.mec_y);
+            zapisi_razliko(&mut buf, g.velocity_y, og.velocity_y);
+            buf.push(g.jumps_allowed);
+        }
+    }
+
+    buf
+}
+
+/// vrne None, ce podatki niso veljavni
+pub fn odkodiraj(podatki: &[u8], osnova: &[KvantiziranoStanje]) -> Option<Vec<KvantiziranoStanje>> {
+    let mut r = Bralnik { podatki };
+    let st = r.varint()? as usize;
+
+    let mut stanja = Vec::with_capacity(st.min(256));
+    for _ in 0..st {
+        let mut s = najdi_osnovo(osnova, r.varint()?);
+        let spremembe = r.u8()?;
+
+        if spremembe & SPREMEMBA_X != 0 { s.x = r.razlika(s.x)?; }
+        if spremembe & SPREMEMBA_Y != 0 { s.y = r.razlika(s.y)?; }
+        if spremembe & SPREMEMBA_ROTACIJA != 0 { s.rotacija = r.u16()?; }
+        if spremembe & SPREMEMBA_ANIM != 0 { s.anim = (r.u8()?, r.u8()?); }
+        if spremembe & SPREMEMBA_NAPAD != 0 { s.attack_time = r.u8()?; }
+        if spremembe & SPREMEMBA_RAZDALJA != 0 { s.razdalja_meca = r.u8()?; }
+        if spremembe & SPREMEMBA_VHOD != 0 { s.zadnji_vhod = r.razlika(s.zadnji_vhod as i32)? as u32; }
+        if spremembe & SPREMEMBA_GIBANJE != 0 {
+            let g = &mut s.gibanje;
+            g.zacetni_x = r.razlika(g.zacetni_x)?;
+            g.zacetni_y = r.razlika(g.zacetni_y)?;
+            g.mec_x = r.razlika(g.mec_x)?;
+            g.mec_y = r.razlika(g.mec_y)?;
+            g.velocity_y = r.razlika(g.velocity_y)?;
+            g.jumps_allowed = r.u8()?;
+        }
+
+        stanja.push(s);
+    }
+
+    if !r.podatki.is_empty() {
+        return None;
+    }
+    Some(stanja)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stanje(id: u32, x: f32, y: f32) -> State {
+        State {
+            id,
+            position: (x, y),
+            rotation: 1.0,
+            anim_frame: (32.0, 64.0),
+            attack_time: 0.25,
+            razdalja_meca: 12.0,
+            zadnji_vhod: 40,
+            gibanje: StanjeGibanja {
+                zacetna_pozicija: (x - 1.0, y),
+                pozicija_meca: (x + 10.0, y + 5.0),
+                velocity_y: -120.5,
+                jumps_allowed: 1,
+            },
+        }
+    }
+
+    #[test]
+    fn cel_posnetek() {
+        let stanja = [KvantiziranoStanje::iz(&stanje(1, 10.0, 20.0)), KvantiziranoStanje::iz(&stanje(2, -5.0, 3.0))];
+        let podatki = zakodiraj(&stanja, &[], 1);
+
+        // gibanje in zadnji vhod dobi le lastnik
+        let tuj = State { zadnji_vhod: 0, gibanje: StanjeGibanja::default(), ..stanje(2, -5.0, 3.0) };
+        assert_eq!(odkodiraj(&podatki, &[]).unwrap(), [stanja[0], KvantiziranoStanje::iz(&tuj)]);
+    }
+
+    #[test]
+    fn razlika_od_osnove() {
+        let osnova = [KvantiziranoStanje::iz(&stanje(1, 10.0, 20.0)), KvantiziranoStanje::iz(&stanje(2, -5.0, 3.0))];
+        let mut premaknjen = stanje(1, 11.5, 20.0);
+        premaknjen.zadnji_vhod = 43;
+        let stanja = [KvantiziranoStanje::iz(&premaknjen), osnova[1]];
+
+        let podatki = zakodiraj(&stanja, &osnova, 1);
+        assert!(podatki.len() < zakodiraj(&stanja, &[], 1).len());
+        assert_eq!(odkodiraj(&podatki, &osnova).unwrap(), stanja);
+    }
+
+    #[test]
+    fn igralca_ni_v_osnovi() {
+        let osnova = [KvantiziranoStanje::iz(&stanje(1, 10.0, 20.0))];
+        let stanja = [osnova[0], KvantiziranoStanje::iz(&stanje(7, 100.0, -50.0))];
+        let podatki = zakodiraj(&stanja, &osnova, 7);
+        assert_eq!(odkodiraj(&podatki, &osnova).unwrap(), stanja);
+    }
+
+    #[test]
+    fn napaka_kvantizacije() {
+        let original = State { rotation: -2.5, attack_time: 0.123, razdalja_meca: 17.3, ..stanje(3, 123.456, -78.9) };
+        let stanja = [KvantiziranoStanje::iz(&original)];
+        let s = odkodiraj(&zakodiraj(&stanja, &[], 3), &[]).unwrap()[0].v_state();
+
+        assert!((s.position.0 - original.position.0).abs() <= KORAK_POZICIJE / 2.0);
+        assert!((s.position.1 - original.position.1).abs() <= KORAK_POZICIJE / 2.0);
+        assert!((s.rotation - original.rotation).abs() <= std::f32::consts::TAU / 65536.0);
+        assert!((s.attack_time - original.attack_time).abs() <= KORAK_NAPADA / 2.0);
+        assert!((s.razdalja_meca - original.razdalja_meca).abs() <= KORAK_RAZDALJE / 2.0);
+        assert!((s.gibanje.velocity_y - original.gibanje.velocity_y).abs() <= KORAK_HITROSTI / 2.0);
+    }
+
+    #[test]
+    fn neveljavni_podatki() {
+        let stanja = [KvantiziranoStanje::iz(&stanje(1, 10.0, 20.0))];
+        let podatki = zakodiraj(&stanja, &[], 1);
+
+        for dolzina in 0..podatki.len() {
+            assert_eq!(odkodiraj(&podatki[..dolzina], &[]), None);
+        }
+        let mut predolgi = podatki.clone();
+        predolgi.push(0);
+        assert_eq!(odkodiraj(&predolgi, &[]), None);
+    }
+}
@@ -0,0 +1,713 @@
+use std::{cell::{Cell, RefCell}, collections::{BTreeMap, HashMap, VecDeque}, io::{self, ErrorKind, Read, Write}, net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket}, rc::{Rc, Weak}, sync::mpsc, time::{Duration, Instant}};
+use crate::Message;
+
+/// najvecja dovoljena velikost enega okvirja (brez glave)
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+/// dolzina glave okvirja, u32 little endian
+const FRAME_HEADER_SIZE: usize = 4;
+
+/// najvecji udp paket, ki ga posljemo ali sprejmemo
+const MAX_UDP_PAKET: usize = 16 * 1024;
+/// cez koliko casa ponovno posljemo nepotrjen zanesljiv paket
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+/// connect tece na glavni niti, zato ne sme cakati na privzet timeout sistema
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// koliko neposlanih bajtov se lahko nabere za tcp povezavo, preden jo prekinemo
+const MAX_IZHODNA_VRSTA: usize = 256 * 1024;
+/// najvec nepotrjenih ali neurejenih zanesljivih paketov na povezavo
+const MAX_NEPOTRJENIH: usize = 1024;
+/// najvec novih udp povezav naenkrat, da ponarejeni naslovi ne zasedejo streznika
+const MAX_NOVIH_UDP_POVEZAV: usize = 64;
+/// toliko casa steje udp povezava za novo, priblizno kot streznik caka na Hello
+const CAS_NOVE_UDP_POVEZAVE: Duration = Duration::from_secs(5);
+
+const PAKET_STANJE: u8 = 0;
+const PAKET_DOGODEK: u8 = 1;
+const PAKET_ACK: u8 = 2;
+const PAKET_DISCONNECT: u8 = 3;
+/// tip (1) + seq (4)
+const UDP_HEADER_SIZE: usize = 5;
+
+fn serializiraj(msg: &Message) -> io::Result<Vec<u8>> {
+    bincode::serialize(msg).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// zapakira sporocilo v okvir: dolzina + bincode
+fn zapakiraj_msg(msg: &Message) -> io::Result<Vec<u8>> {
+    let data = serializiraj(msg)?;
+    let mut buf = Vec::with_capacity(FRAME_HEADER_SIZE + data.len());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&data);
+    Ok(buf)
+}
+
+#[derive(Debug)]
+pub enum RecvError {
+    Disconnect,
+    PrevelikOkvir(usize),
+    /// udp paket s prekratko glavo ali neznanim tipom
+    NeveljavenPaket(String),
+    /// prevec zanesljivih paketov caka na manjkajocega
+    PrevecNeurejenih(usize),
+    NeveljavenOkvir(Box<bincode::ErrorKind>),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecvError::Disconnect => write!(f, "disconnected"),
+            RecvError::PrevelikOkvir(len) => write!(f, "invalid frame length {}", len),
+            RecvError::NeveljavenPaket(razlog) => write!(f, "invalid packet: {}", razlog),
+            RecvError::PrevecNeurejenih(st) => write!(f, "too many out-of-order packets ({})", st),
+            RecvError::NeveljavenOkvir(e) => write!(f, "malformed frame: {}", e),
+            RecvError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+fn io_recv_error(e: io::Error) -> RecvError {
+    match e.kind() {
+        ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::ConnectionRefused => {
+            RecvError::Disconnect
+        },
+        _ => RecvError::Io(e),
+    }
+}
+
+/// zbira prejete bajte, dokler ni na voljo celoten okvir
+struct FrameReader {
+    buf: Vec<u8>,
+}
+
+impl FrameReader {
+    fn new() -> FrameReader {
+        FrameReader { buf: Vec::new() }
+    }
+
+    /// prebere vse, kar je trenutno na voljo v socketu
+    fn preberi(&mut self, stream: &mut TcpStream) -> Result<(), RecvError> {
+        let mut tmp = [0u8; 4096];
+        loop {
+            match stream.read(&mut tmp) {
+                Ok(0) => return Err(RecvError::Disconnect),
+                Ok(n) => self.buf.extend_from_slice(&tmp[..n]),
+                Err(e) => {
+                    match e.kind() {
+                        ErrorKind::WouldBlock => return Ok(()),
+                        ErrorKind::Interrupted => continue,
+                        _ => return Err(io_recv_error(e)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// vrne naslednje celotno sporocilo, ce je ze prispelo
+    fn naslednji_msg(&mut self) -> Result<Option<Message>, RecvError> {
+        if self.buf.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        header.copy_from_slice(&self.buf[..FRAME_HEADER_SIZE]);
+        let len = u32::from_le_bytes(header) as usize;
+        if len == 0 || len > MAX_FRAME_SIZE {
+            return Err(RecvError::PrevelikOkvir(len));
+        }
+
+        if self.buf.len() < FRAME_HEADER_SIZE + len {
+            return Ok(None);
+        }
+
+        let msg = bincode::deserialize(&self.buf[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len]);
+        self.buf.drain(..FRAME_HEADER_SIZE + len);
+        match msg {
+            Ok(msg) => Ok(Some(msg)),
+            Err(e) => Err(RecvError::NeveljavenOkvir(e)),
+        }
+    }
+}
+
+/// ena povezava med streznikom in odjemalcem, ne glede na nacin prenosa
+pub trait Povezava {
+    fn addr(&self) -> SocketAddr;
+
+    fn send(&mut self, msg: &Message) -> io::Result<()>;
+
+    /// prebere prispele podatke, napaka pomeni, da je povezava prekinjena
+    fn preberi(&mut self) -> Result<(), RecvError>;
+
+    /// vrne naslednje celotno sporocilo, ce je ze prispelo
+    fn naslednji_msg(&mut self) -> Result<Option<Message>, RecvError>;
+
+    /// poskusi poslati, kar se je nabralo, ker socket ni sprejel vsega
+    fn poslji_cakajoce(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// povezava v istem procesu, torej od gostitelja
+    fn lokalna(&self) -> bool {
+        false
+    }
+
+    /// zapre povezavo, druga stran dobi RecvError::Disconnect
+    fn disconnect(&mut self);
+}
+
+/// sprejema nove povezave na strezniku
+pub trait Poslusalec {
+    fn sprejmi(&mut self) -> Vec<Box<dyn Povezava>>;
+}
+
+pub struct TcpPovezava {
+    stream: TcpStream,
+    frames: FrameReader,
+    /// bajti, ki jih socket se ni sprejel
+    izhod: VecDeque<u8>,
+}
+
+impl TcpPovezava {
+    fn new(stream: TcpStream) -> io::Result<TcpPovezava> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(TcpPovezava { stream, frames: FrameReader::new(), izhod: VecDeque::new() })
+    }
+}
+
+impl Povezava for TcpPovezava {
+    fn addr(&self) -> SocketAddr {
+        self.stream.peer_addr().unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)))
+    }
+
+    fn send(&mut self, msg: &Message) -> io::Result<()> {
+        let okvir = zapakiraj_msg(msg)?;
+        if self.izhod.len() + okvir.len() > MAX_IZHODNA_VRSTA {
+            return Err(io::Error::new(ErrorKind::WouldBlock, "send queue full, peer is too slow"));
+        }
+        self.izhod.extend(okvir);
+        self.poslji_cakajoce()
+    }
+
+    fn poslji_cakajoce(&mut self) -> io::Result<()> {
+        while !self.izhod.is_empty() {
+            let (zacetek, _) = self.izhod.as_slices();
+            match self.stream.write(zacetek) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "connection closed")),
+                Ok(n) => { self.izhod.drain(..n); },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn preberi(&mut self) -> Result<(), RecvError> {
+        self.frames.preberi(&mut self.stream)
+    }
+
+    fn naslednji_msg(&mut self) -> Result<Option<Message>, RecvError> {
+        self.frames.naslednji_msg()
+    }
+
+    fn disconnect(&mut self) {
+        let _ = self.poslji_cakajoce();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// prejeti paketi ene povezave na strezniku
+type Paketi = RefCell<VecDeque<Vec<u8>>>;
+type Nabiralnik = Rc<Paketi>;
+
+/// odjemalcev socket je spojen s streznikom, streznikov pa ne
+fn poslji_udp(socket: &UdpSocket, addr: SocketAddr, spojen: bool, paket: &[u8]) -> io::Result<()> {
+    if spojen {
+        socket.send(paket)?;
+    } else {
+        socket.send_to(paket, addr)?;
+    }
+    Ok(())
+}
+
+/// udp povezava z dvema kanaloma:
+/// stanja so nezanesljiva, stara se zavrzejo,
+/// dogodki so zanesljivi in urejeni (ack + ponovno posiljanje)
+pub struct UdpPovezava {
+    socket: Rc<UdpSocket>,
+    addr: SocketAddr,
+    /// na strezniku si vse povezave delijo en socket, zato pakete razdeli poslusalec
+    inbox: Option<Nabiralnik>,
+
+    naslednji_seq_stanje: u32,
+    zadnji_prejet_seq_stanje: Option<u32>,
+
+    naslednji_seq_dogodek: u32,
+    nepotrjeni: VecDeque<(u32, Vec<u8>, Instant)>,
+    zadnji_dostavljen_dogodek: u32,
+    neurejeni: BTreeMap<u32, Message>,
+
+    prejeto: VecDeque<Message>,
+    prekinjeno: bool,
+}
+
+impl UdpPovezava {
+    fn new(socket: Rc<UdpSocket>, addr: SocketAddr, inbox: Option<Nabiralnik>) -> UdpPovezava {
+        UdpPovezava {
+            socket,
+            addr,
+            inbox,
+            naslednji_seq_stanje: 0,
+            zadnji_prejet_seq_stanje: None,
+            naslednji_seq_dogodek: 0,
+            nepotrjeni: VecDeque::new(),
+            zadnji_dostavljen_dogodek: 0,
+            neurejeni: BTreeMap::new(),
+            prejeto: VecDeque::new(),
+            prekinjeno: false,
+        }
+    }
+
+    fn poslji_paket(&self, paket: &[u8]) -> io::Result<()> {
+        poslji_udp(&self.socket, self.addr, self.inbox.is_none(), paket)
+    }
+
+    fn obdelaj_paket(&mut self, paket: &[u8]) -> Result<(), RecvError> {
+        if paket.first() == Some(&PAKET_DISCONNECT) {
+            self.prekinjeno = true;
+            return Ok(());
+        }
+
+        if paket.len() < UDP_HEADER_SIZE {
+            return Err(RecvError::NeveljavenPaket(format!("header too short ({} bytes)", paket.len())));
+        }
+        let tip = paket[0];
+        let mut seq_bytes = [0u8; 4];
+        seq_bytes.copy_from_slice(&paket[1..UDP_HEADER_SIZE]);
+        let seq = u32::from_le_bytes(seq_bytes);
+        let data = &paket[UDP_HEADER_SIZE..];
+
+        match tip {
+            PAKET_STANJE => {
+                if self.zadnji_prejet_seq_stanje.is_some_and(|zadnji| seq <= zadnji) {
+                    return Ok(()); // star paket
+                }
+                self.zadnji_prejet_seq_stanje = Some(seq);
+                let msg = bincode::deserialize(data).map_err(RecvError::NeveljavenOkvir)?;
+                self.prejeto.push_back(msg);
+            },
+            PAKET_DOGODEK => {
+                let mut ack = vec![PAKET_ACK];
+                ack.extend_from_slice(&seq.to_le_bytes());
+                let _ = self.poslji_paket(&ack);
+
+                if seq <= self.zadnji_dostavljen_dogodek || self.neurejeni.contains_key(&seq) {
+                    return Ok(()); // duplikat
+                }
+                if self.neurejeni.len() >= MAX_NEPOTRJENIH {
+                    return Err(RecvError::PrevecNeurejenih(self.neurejeni.len()));
+                }
+                let msg = bincode::deserialize(data).map_err(RecvError::NeveljavenOkvir)?;
+                self.neurejeni.insert(seq, msg);
+
+                while let Some(msg) = self.neurejeni.remove(&(self.zadnji_dostavljen_dogodek + 1)) {
+                    self.zadnji_dostavljen_dogodek += 1;
+                    self.prejeto.push_back(msg);
+                }
+            },
+            PAKET_ACK => {
+                self.nepotrjeni.retain(|(s, _, _)| *s != seq);
+            },
+            _ => return Err(RecvError::NeveljavenPaket(format!("unknown type {}", tip))),
+        }
+        Ok(())
+    }
+}
+
+impl Povezava for UdpPovezava {
+    fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn send(&mut self, msg: &Message) -> io::Result<()> {
+        let zanesljivo = msg.zanesljivo();
+        let (tip, seq) = if zanesljivo {
+            self.naslednji_seq_dogodek += 1;
+            (PAKET_DOGODEK, self.naslednji_seq_dogodek)
+        } else {
+            self.naslednji_seq_stanje += 1;
+            (PAKET_STANJE, self.naslednji_seq_stanje)
+        };
+
+        let mut paket = vec![tip];
+        paket.extend_from_slice(&seq.to_le_bytes());
+        paket.append(&mut serializiraj(msg)?);
+        if paket.len() > MAX_UDP_PAKET {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("udp packet too large ({} bytes)", paket.len())));
+        }
+
+        if zanesljivo {
+            if self.nepotrjeni.len() >= MAX_NEPOTRJENIH {
+                return Err(io::Error::new(ErrorKind::WouldBlock, "too many unacknowledged packets"));
+            }
+            self.nepotrjeni.push_back((seq, paket.clone(), Instant::now()));
+        }
+
+        match self.poslji_paket(&paket) {
+            // zanesljiv paket bo ponovno poslan, stanje pa je tako ali tako nezanesljivo
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            r => r,
+        }
+    }
+
+    fn preberi(&mut self) -> Result<(), RecvError> {
+        let mut paketi = Vec::new();
+        if let Some(inbox) = &self.inbox {
+            paketi.extend(inbox.borrow_mut().drain(..));
+        } else {
+            let mut buf = [0u8; MAX_UDP_PAKET];
+            loop {
+                match self.socket.recv(&mut buf) {
+                    Ok(n) => paketi.push(buf[..n].to_vec()),
+                    Err(e) => {
+                        match e.kind() {
+                            ErrorKind::WouldBlock => break,
+                            ErrorKind::Interrupted => continue,
+                            _ => return Err(io_recv_error(e)),
+                        }
+                    }
+                }
+            }
+        }
+
+        for paket in paketi {
+            self.obdelaj_paket(&paket)?;
+        }
+
+        let zdaj = Instant::now();
+        let spojen = self.inbox.is_none();
+        for (_, paket, poslano) in self.nepotrjeni.iter_mut() {
+            if zdaj.duration_since(*poslano) > RESEND_INTERVAL {
+                *poslano = zdaj;
+                let _ = poslji_udp(&self.socket, self.addr, spojen, paket);
+            }
+        }
+        // tisino zazna heartbeat v Server in Client, ki upostevata nastavljen timeout
+        Ok(())
+    }
+
+    fn naslednji_msg(&mut self) -> Result<Option<Message>, RecvError> {
+        if let Some(msg) = self.prejeto.pop_front() {
+            return Ok(Some(msg));
+        }
+        if self.prekinjeno {
+            return Err(RecvError::Disconnect);
+        }
+        Ok(None)
+    }
+
+    fn disconnect(&mut self) {
+        if self.prekinjeno {
+            return;
+        }
+        // se zadnjic poskusimo poslati nepotrjene dogodke (npr. Reject)
+        for (_, paket, _) in &self.nepotrjeni {
+            let _ = self.poslji_paket(paket);
+        }
+        let _ = self.poslji_paket(&[PAKET_DISCONNECT]);
+        self.prekinjeno = true;
+    }
+}
+
+impl Drop for UdpPovezava {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+pub fn connect_tcp(addr: &str, port: u16) -> io::Result<Box<dyn Povezava>> {
+    let mut napaka = io::Error::new(ErrorKind::NotFound, "address not found");
+    for naslov in (addr, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&naslov, TCP_CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(Box::new(TcpPovezava::new(stream)?)),
+            Err(e) => napaka = e,
+        }
+    }
+    Err(napaka)
+}
+
+pub fn connect_udp(addr: &str, port: u16) -> io::Result<Box<dyn Povezava>> {
+    let server_addr = (addr, port).to_socket_addrs()?.next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "address not found"))?;
+    let bind_addr: SocketAddr = if server_addr.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(server_addr)?;
+    socket.set_nonblocking(true)?;
+    Ok(Box::new(UdpPovezava::new(Rc::new(socket), server_addr, None)))
+}
+
+/// poslusa na istem portu za tcp in udp
+pub fn poslusaj(addr: SocketAddr) -> io::Result<Vec<Box<dyn Poslusalec>>> {
+    Ok(vec![
+        Box::new(TcpPoslusalec::bind(addr)?),
+        Box::new(UdpPoslusalec::bind(addr)?),
+    ])
+}
+
+pub struct TcpPoslusalec {
+    listener: TcpListener,
+}
+
+impl TcpPoslusalec {
+    pub fn bind(addr: SocketAddr) -> io::Result<TcpPoslusalec> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(TcpPoslusalec { listener })
+    }
+}
+
+impl Poslusalec for TcpPoslusalec {
+    fn sprejmi(&mut self) -> Vec<Box<dyn Povezava>> {
+        let mut nove: Vec<Box<dyn Povezava>> = Vec::new();
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _addr)) => {
+                    match TcpPovezava::new(stream) {
+                        Ok(tcp) => nove.push(Box::new(tcp)),
+                        Err(e) => eprintln!("error preparing tcp socket: {}", e),
+                    }
+                },
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        eprintln!("error in TcpListener incoming: {}", e);
+                    }
+                    break;
+                }
+            }
+        }
+
+        nove
+    }
+}
+
+pub struct UdpPoslusalec {
+    socket: Rc<UdpSocket>,
+    /// kam posredujemo pakete za posamezen naslov in kdaj je povezava nastala
+    povezave: HashMap<SocketAddr, (Weak<Paketi>, Instant)>,
+}
+
+impl UdpPoslusalec {
+    pub fn bind(addr: SocketAddr) -> io::Result<UdpPoslusalec> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpPoslusalec { socket: Rc::new(socket), povezave: HashMap::new() })
+    }
+}
+
+impl Poslusalec for UdpPoslusalec {
+    /// vrne nove povezave in razdeli prispele pakete obstojecim
+    fn sprejmi(&mut self) -> Vec<Box<dyn Povezava>> {
+        let mut nove: Vec<Box<dyn Povezava>> = Vec::new();
+
+        // streznik je povezavo zavrgel
+        self.povezave.retain(|_, (inbox, _)| inbox.strong_count() > 0);
+        let mut st_novih = self.povezave.values()
+            .filter(|(_, nastala)| nastala.elapsed() < CAS_NOVE_UDP_POVEZAVE)
+            .count();
+
+        let mut buf = [0u8; MAX_UDP_PAKET];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, addr)) => {
+                    let paket = buf[..n].to_vec();
+                    if let Some(inbox) = self.povezave.get(&addr).and_then(|(inbox, _)| inbox.upgrade()) {
+                        inbox.borrow_mut().push_back(paket);
+                        continue;
+                    }
+                    if paket.first() == Some(&PAKET_DISCONNECT) || paket.first() == Some(&PAKET_ACK) {
+                        continue;
+                    }
+                    if st_novih >= MAX_NOVIH_UDP_POVEZAV {
+                        continue;
+                    }
+                    st_novih += 1;
+
+                    let inbox = Rc::new(RefCell::new(VecDeque::from([paket])));
+                    self.povezave.insert(addr, (Rc::downgrade(&inbox), Instant::now()));
+                    nove.push(Box::new(UdpPovezava::new(self.socket.clone(), addr, Some(inbox))));
+                },
+                Err(e) => {
+                    match e.kind() {
+                        ErrorKind::WouldBlock => break,
+                        // na windows udp socket vrne napako, ce je bil prejsnji paket zavrnjen
+                        ErrorKind::ConnectionReset | ErrorKind::Interrupted => continue,
+                        _ => {
+                            eprintln!("error in UdpSocket recv_from: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        nove
+    }
+}
+
+/// povezava znotraj istega procesa, npr. za teste ali lokalnega igralca
+pub struct LoopbackPovezava {
+    tx: Option<mpsc::Sender<Message>>,
+    rx: mpsc::Receiver<Message>,
+    prejeto: VecDeque<Message>,
+    prekinjeno: bool,
+    addr: SocketAddr,
+}
+
+impl LoopbackPovezava {
+    /// vrne oba konca nove povezave
+    pub fn par(id: u16) -> (LoopbackPovezava, LoopbackPovezava) {
+        let (tx_a, rx_a) = mpsc::channel();
+        let (tx_b, rx_b) = mpsc::channel();
+        let addr = SocketAddr::from(([127, 0, 0, 1], id));
+        let a = LoopbackPovezava { tx: Some(tx_a), rx: rx_b, prejeto: VecDeque::new(), prekinjeno: false, addr };
+        let b = LoopbackPovezava { tx: Some(tx_b), rx: rx_a, prejeto: VecDeque::new(), prekinjeno: false, addr };
+        (a, b)
+    }
+}
+
+impl Povezava for LoopbackPovezava {
+    fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn lokalna(&self) -> bool {
+        true
+    }
+
+    fn send(&mut self, msg: &Message) -> io::Result<()> {
+        match &self.tx {
+            Some(tx) => tx.send(msg.clone()).map_err(|_| io::Error::from(ErrorKind::BrokenPipe)),
+            None => Err(io::Error::from(ErrorKind::NotConnected)),
+        }
+    }
+
+    fn preberi(&mut self) -> Result<(), RecvError> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => self.prejeto.push_back(msg),
+                Err(mpsc::TryRecvError::Empty) => return Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.prekinjeno = true;
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    fn naslednji_msg(&mut self) -> Result<Option<Message>, RecvError> {
+        if let Some(msg) = self.prejeto.pop_front() {
+            return Ok(Some(msg));
+        }
+        if self.prekinjeno {
+            return Err(RecvError::Disconnect);
+        }
+        Ok(None)
+    }
+
+    fn disconnect(&mut self) {
+        self.tx = None;
+        self.prekinjeno = true;
+    }
+}
+
+/// sprejema povezave, ki jih ustvari LoopbackConnector
+pub struct LoopbackPoslusalec {
+    nove: mpsc::Receiver<LoopbackPovezava>,
+}
+
+#[derive(Clone)]
+pub struct LoopbackConnector {
+    nove: mpsc::Sender<LoopbackPovezava>,
+    naslednji_id: Rc<Cell<u16>>,
+}
+
+impl LoopbackPoslusalec {
+    pub fn new() -> (LoopbackPoslusalec, LoopbackConnector) {
+        let (tx, rx) = mpsc::channel();
+        let connector = LoopbackConnector { nove: tx, naslednji_id: Rc::new(Cell::new(1)) };
+        (LoopbackPoslusalec { nove: rx }, connector)
+    }
+}
+
+impl Poslusalec for LoopbackPoslusalec {
+    fn sprejmi(&mut self) -> Vec<Box<dyn Povezava>> {
+        self.nove.try_iter()
+            .map(|p| Box::new(p) as Box<dyn Povezava>)
+            .collect()
+    }
+}
+
+impl LoopbackConnector {
+    pub fn connect(&self) -> io::Result<Box<dyn Povezava>> {
+        let id = self.naslednji_id.get();
+        self.naslednji_id.set(id.wrapping_add(1));
+        let (odjemalec, streznik) = LoopbackPovezava::par(id);
+        self.nove.send(streznik).map_err(|_| io::Error::from(ErrorKind::ConnectionRefused))?;
+        Ok(Box::new(odjemalec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn okvir() -> Vec<u8> {
+        zapakiraj_msg(&Message::Reject { reason: "test".to_string() }).unwrap()
+    }
+
+    #[test]
+    fn razdeljen_okvir() {
+        let okvir = okvir();
+        let mut frames = FrameReader::new();
+
+        // glava in podatki prispejo v vec kosih
+        for kos in okvir[..okvir.len() - 1].chunks(3) {
+            frames.buf.extend_from_slice(kos);
+            assert!(matches!(frames.naslednji_msg(), Ok(None)));
+        }
+        frames.buf.push(*okvir.last().unwrap());
+        assert!(matches!(frames.naslednji_msg(), Ok(Some(Message::Reject { reason })) if reason == "test"));
+        assert!(frames.buf.is_empty());
+    }
+
+    #[test]
+    fn vec_okvirjev_naenkrat() {
+        let mut frames = FrameReader::new();
+        frames.buf.extend(okvir());
+        frames.buf.extend(okvir());
+        assert!(matches!(frames.naslednji_msg(), Ok(Some(Message::Reject { .. }))));
+        assert!(matches!(frames.naslednji_msg(), Ok(Some(Message::Reject { .. }))));
+        assert!(matches!(frames.naslednji_msg(), Ok(None)));
+    }
+
+    #[test]
+    fn neveljavna_dolzina() {
+        let mut frames = FrameReader::new();
+        frames.buf.extend_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(frames.naslednji_msg(), Err(RecvError::PrevelikOkvir(0))));
+
+        let mut frames = FrameReader::new();
+        frames.buf.extend_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes());
+        assert!(matches!(frames.naslednji_msg(), Err(RecvError::PrevelikOkvir(n)) if n == MAX_FRAME_SIZE + 1));
+    }
+
+    #[test]
+    fn neveljavna_vsebina() {
+        let mut frames = FrameReader::new();
+        frames.buf.extend_from_slice(&4u32.to_le_bytes());
+        frames.buf.extend_from_slice(&[0xff; 4]);
+        assert!(matches!(frames.naslednji_msg(), Err(RecvError::NeveljavenOkvir(_))));
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Mutex;
+
+use vegovci_multiplayer::*;
+
+/// fizika in pop-up sporocila so globalna, zato testi ne smejo teci hkrati
+static ZAKLEP: Mutex<()> = Mutex::new(());
+
+fn nov_streznik() -> (Server, LoopbackConnector) {
+    physics::init();
+    let (poslusalec, connector) = LoopbackPoslusalec::new();
+    (Server::with_listeners(vec![Box::new(poslusalec)]), connector)
+}
+
+fn povezi(connector: &LoopbackConnector, ime: &str) -> Client {
+    Client::with_connection(connector.connect().unwrap(), ime.to_string(), None, false)
+}
+
+/// nekaj tickov streznika in odjemalcev
+fn simuliraj(server: &mut Server, clients: &mut [&mut Client]) {
+    for _ in 0..5 {
+        server.tick(TICK_TIME);
+        for client in clients.iter_mut() {
+            client.recv();
+        }
+    }
+}
+
+fn imena(client: &Client) -> Vec<String> {
+    let mut imena: Vec<String> = client.get_leaderboard_data().into_iter().map(|(ime, _, _)| ime).collect();
+    imena.sort();
+    imena
+}
+
+#[test]
+fn handshake_in_user_info() {
+    let _zaklep = ZAKLEP.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut server, connector) = nov_streznik();
+    let mut ana = povezi(&connector, "ana");
+    let mut bor = povezi(&connector, "bor");
+
+    simuliraj(&mut server, &mut [&mut ana, &mut bor]);
+
+    assert!(ana.status == ClientStatus::Povezan);
+    assert!(bor.status == ClientStatus::Povezan);
+    assert_ne!(ana.id, bor.id);
+    assert_eq!(imena(&ana), ["ana", "bor"]);
+    assert_eq!(imena(&bor), ["ana", "bor"]);
+}
+
+#[test]
+fn podvojeno_ime_dobi_pripono() {
+    let _zaklep = ZAKLEP.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut server, connector) = nov_streznik();
+    let mut ana = povezi(&connector, "ana");
+    let mut druga_ana = povezi(&connector, "ANA");
+
+    simuliraj(&mut server, &mut [&mut ana, &mut druga_ana]);
+
+    assert_eq!(ana.ime(), "ana");
+    assert_eq!(druga_ana.ime(), "ANA_2");
+    assert_eq!(imena(&ana), ["ANA_2", "ana"]);
+}
+
+#[test]
+fn prekinitev_odjemalca() {
+    let _zaklep = ZAKLEP.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut server, connector) = nov_streznik();
+    let mut ana = povezi(&connector, "ana");
+    let mut bor = povezi(&connector, "bor");
+    simuliraj(&mut server, &mut [&mut ana, &mut bor]);
+    assert!(server.izvedi_ukaz("/players").contains("bor"));
+
+    drop(bor);
+    simuliraj(&mut server, &mut [&mut ana]);
+
+    // bor ni vec povezan, a njegovo mesto caka na vrnitev
+    assert!(!server.izvedi_ukaz("/players").contains("bor"));
+    assert!(ana.status == ClientStatus::Povezan);
+    assert_eq!(imena(&ana), ["ana", "bor"]);
+}
+
+#[test]
+fn napacna_verzija_je_zavrnjena() {
+    let _zaklep = ZAKLEP.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut server, connector) = nov_streznik();
+    let mut povezava = connector.connect().unwrap();
+    povezava.send(&Message::Hello { protocol_version: 0, game_version: "0".to_string() }).unwrap();
+
+    simuliraj(&mut server, &mut []);
+
+    povezava.preberi().unwrap();
+    match povezava.naslednji_msg() {
+        Ok(Some(Message::Reject { reason })) => assert!(reason.contains("protocol version")),
+        _ => panic!("expected Reject"),
+    }
+}
+
+#[test]
+fn set_zavrne_neveljavne_vrednosti() {
+    let _zaklep = ZAKLEP.lock().unwrap_or_else(|e| e.into_inner());
+    let (mut server, _connector) = nov_streznik();
+
+    for ukaz in ["/set timeout 1e20", "/set max_rewind inf", "/set respawn_time NaN", "/set send_rate -1"] {
+        assert!(server.izvedi_ukaz(ukaz).starts_with("invalid value"), "{}", ukaz);
+    }
+    assert_eq!(server.izvedi_ukaz("/set timeout 5"), "timeout = 5");
+}